@@ -1,8 +1,13 @@
 use crate::reader::Reader;
+use crate::writer::content::LineEnding;
+use crate::writer::encoding::Encoding;
+use crate::writer::file;
+use crate::writer::swap::{self, SwapFile};
 use crate::writer::Writer;
 use crate::{prompt, QUIT_TIMES};
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use std::cmp;
+use std::fs;
 use std::path::PathBuf;
 
 /// Editor struct
@@ -12,6 +17,12 @@ pub struct Editor {
     quit_time: u8,
 }
 
+impl Default for Editor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Editor {
     /// constructor
     pub fn new() -> Self {
@@ -23,14 +34,14 @@ impl Editor {
     }
 
     /// process keypress
-    pub fn process_keypress(&mut self) -> crossterm::Result<bool> {
-        match self.reader.read_key()? {
+    pub fn process_keypress(&mut self, key: KeyEvent) -> crossterm::Result<bool> {
+        match key {
             // press Ctrl-Q to quit
             KeyEvent {
                 code: KeyCode::Char('q'),
                 modifiers: KeyModifiers::CONTROL,
             } => {
-                if self.writer.dirty > 0 && self.quit_time > 0 {
+                if self.writer.is_dirty() && self.quit_time > 0 {
                     self.writer.status_message.set_message(format!(
                         "WARNING!! File has unsaved changes. Press Ctrl-Q {} more times to quit.",
                         self.quit_time
@@ -40,7 +51,7 @@ impl Editor {
                 }
                 return Ok(false);
             }
-            // direction controller
+            // direction controller, selecting with Shift
             KeyEvent {
                 code:
                     direction @ (KeyCode::Up
@@ -49,15 +60,58 @@ impl Editor {
                     | KeyCode::Right
                     | KeyCode::Home
                     | KeyCode::End),
-                modifiers: KeyModifiers::NONE,
+                modifiers: modifiers @ (KeyModifiers::NONE | KeyModifiers::SHIFT),
             } => {
+                self.writer.select(modifiers == KeyModifiers::SHIFT);
                 self.writer.move_cursor(direction);
             }
+            // select a block of columns
+            KeyEvent {
+                code: direction @ (KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right),
+                modifiers,
+            } if modifiers == KeyModifiers::ALT | KeyModifiers::SHIFT => {
+                self.writer.extend_block(direction)
+            }
+            // move by words and paragraphs, or to the start and end of the file
+            KeyEvent {
+                code:
+                    direction @ (KeyCode::Up
+                    | KeyCode::Down
+                    | KeyCode::Left
+                    | KeyCode::Right
+                    | KeyCode::Home
+                    | KeyCode::End),
+                modifiers,
+            } if modifiers - KeyModifiers::SHIFT == KeyModifiers::CONTROL => {
+                self.writer.select(modifiers.contains(KeyModifiers::SHIFT));
+                self.writer.move_cursor_far(direction)?;
+            }
+            // add a cursor on the row above or below the cursors
+            KeyEvent {
+                code: direction @ (KeyCode::Up | KeyCode::Down),
+                modifiers,
+            } if modifiers - KeyModifiers::CONTROL == KeyModifiers::ALT => self
+                .writer
+                .add_cursor_vertically(direction == KeyCode::Down),
+            // select the word, then add a cursor at its next occurrence
+            KeyEvent {
+                code: KeyCode::Char('d'),
+                modifiers: KeyModifiers::CONTROL,
+            } => self.writer.add_cursor_at_next_match(),
+            // a cursor for every row of the selection
+            KeyEvent {
+                code: KeyCode::Char('I'),
+                modifiers,
+            } if modifiers - KeyModifiers::SHIFT == KeyModifiers::ALT => {
+                self.writer.split_selection()
+            }
             // Scrolling with PageUp and PageDown
             KeyEvent {
                 code: val @ (KeyCode::PageUp | KeyCode::PageDown),
-                modifiers: KeyModifiers::NONE,
+                modifiers: modifiers @ (KeyModifiers::NONE | KeyModifiers::SHIFT),
             } => {
+                self.writer.single_cursor();
+                self.writer.select(modifiers == KeyModifiers::SHIFT);
                 if matches!(val, KeyCode::PageUp) {
                     self.writer.cursor_controller.cursor_y =
                         self.writer.cursor_controller.row_offset;
@@ -80,55 +134,201 @@ impl Editor {
                 code: KeyCode::Char('s'),
                 modifiers: KeyModifiers::CONTROL,
             } => {
-                if matches!(self.writer.editor_rows.filename, None) {
-                    let prompt = prompt!(&mut self.writer, "Save as: {} (Esc to cancel)")
-                        .map(|it| it.into());
-                    if let None = prompt {
+                let mut save_as = None;
+                if self.writer.editor_rows.filename.is_none() {
+                    save_as =
+                        prompt!(&mut self.writer, "Save as: {} (Esc to cancel)").map(PathBuf::from);
+                    if save_as.is_none() {
+                        self.writer
+                            .status_message
+                            .set_message("Save Aborted".into());
+                        return Ok(true);
+                    }
+                } else if self.writer.editor_rows.changed_on_disk() {
+                    let answer = self.writer.choose(
+                        "WARNING!! File changed on disk since it was read. Overwrite it? (y/n)",
+                        &['y', 'n'],
+                    )?;
+                    if answer != Some('y') {
                         self.writer
                             .status_message
                             .set_message("Save Aborted".into());
                         return Ok(true);
                     }
-                    prompt
-                        .as_ref()
-                        .and_then(|path: &PathBuf| path.extension())
-                        .and_then(|ext| ext.to_str())
-                        .map(|ext| {
-                            Writer::select_syntax(ext).map(|syntax| {
+                }
+                let saved = match save_as.clone() {
+                    Some(path) => self.writer.editor_rows.save_as(path, self.writer.backup),
+                    None => self.writer.editor_rows.save(self.writer.backup),
+                };
+                match saved {
+                    Ok(len) => {
+                        // a new name may come with a file type
+                        if let Some(ext) = save_as
+                            .as_ref()
+                            .and_then(|path| path.extension())
+                            .and_then(|ext| ext.to_str())
+                        {
+                            if let Some(syntax) = Writer::select_syntax(ext) {
                                 let highlight = self.writer.syntax_highlight.insert(syntax);
-                                for i in 0..self.writer.editor_rows.number_of_rows() {
-                                    highlight.update_syntax(
-                                        i,
-                                        &mut self.writer.editor_rows.row_contents,
-                                    );
-                                }
-                            })
-                        });
-                    self.writer.editor_rows.filename = prompt;
-                }
-                self.writer.editor_rows.save().map(|len| {
-                    self.writer
+                                self.writer
+                                    .editor_rows
+                                    .update_all_syntax(highlight.as_ref());
+                                self.writer.outline = None;
+                            }
+                        }
+                        self.writer
+                            .status_message
+                            .set_message(format!("{} bytes written to disk", len));
+                        self.writer.editor_rows.history.mark_saved();
+                        self.writer.swap_file.remove();
+                    }
+                    Err(err) => self
+                        .writer
                         .status_message
-                        .set_message(format!("{} bytes written to disk", len));
-                    self.writer.dirty = 0;
-                })?;
+                        .set_message(format!("Can't save! I/O error: {}", err)),
+                }
+            }
+            // undo and redo
+            KeyEvent {
+                code: KeyCode::Char('z'),
+                modifiers: KeyModifiers::CONTROL,
+            } => self.writer.undo(),
+            KeyEvent {
+                code: KeyCode::Char('y'),
+                modifiers: KeyModifiers::CONTROL,
+            } => self.writer.redo(),
+            // incremental search
+            KeyEvent {
+                code: KeyCode::Char('f'),
+                modifiers: KeyModifiers::CONTROL,
+            } => self.writer.find()?,
+            // go to line
+            KeyEvent {
+                code: KeyCode::Char('g'),
+                modifiers: KeyModifiers::CONTROL,
+            } => {
+                if let Some(location) =
+                    prompt!(&mut self.writer, "Go to line[:col]: {} (Esc to cancel)")
+                {
+                    match parse_location(&location) {
+                        Some((line, column)) => {
+                            self.writer.record_jump();
+                            self.writer.go_to(line, column);
+                            self.writer.center_view();
+                        }
+                        None => self
+                            .writer
+                            .status_message
+                            .set_message(format!("Invalid line: {}", location)),
+                    }
+                }
+            }
+            // jump to the matching bracket
+            KeyEvent {
+                code: KeyCode::Char('b'),
+                modifiers: KeyModifiers::CONTROL,
+            } => self.writer.jump_to_bracket(),
+            // open a file of the project
+            KeyEvent {
+                code: KeyCode::Char('p'),
+                modifiers: KeyModifiers::CONTROL,
+            } => {
+                if let Some(file) = self.writer.pick_file()? {
+                    self.open(file)?;
+                }
+            }
+            // go to a symbol of the file
+            KeyEvent {
+                code: KeyCode::Char('o'),
+                modifiers: KeyModifiers::CONTROL,
+            } => self.writer.go_to_symbol()?,
+            // set and go to named marks
+            KeyEvent {
+                code: key @ KeyCode::Char('m' | 'j'),
+                modifiers: KeyModifiers::ALT,
+            } => {
+                let names: Vec<char> = ('a'..='z').chain('A'..='Z').collect();
+                let question = if key == KeyCode::Char('m') {
+                    "Set mark: press a letter (Esc to cancel)"
+                } else {
+                    "Go to mark: press a letter (Esc to cancel)"
+                };
+                if let Some(name) = self.writer.choose(question, &names)? {
+                    if key == KeyCode::Char('m') {
+                        self.writer.set_mark(name);
+                    } else {
+                        self.writer.go_to_mark(name);
+                    }
+                }
+            }
+            // go back and forward in the jump list
+            KeyEvent {
+                code: direction @ (KeyCode::Left | KeyCode::Right),
+                modifiers: KeyModifiers::ALT,
+            } => self.writer.jump(direction == KeyCode::Left),
+            // find and replace
+            KeyEvent {
+                code: KeyCode::Char('r'),
+                modifiers: KeyModifiers::CONTROL,
+            } => self.writer.replace()?,
+            // execute command
+            KeyEvent {
+                code: KeyCode::Char('e'),
+                modifiers: KeyModifiers::CONTROL,
+            } => {
+                if let Some(command) = prompt!(&mut self.writer, "Command: {} (Esc to cancel)") {
+                    self.execute_command(&command)?;
+                }
             }
             // delete char
             KeyEvent {
-                code: key @ (KeyCode::Backspace | KeyCode::Delete),
+                code: KeyCode::Backspace,
+                modifiers: KeyModifiers::NONE,
+            } => self.writer.delete_char(),
+            KeyEvent {
+                code: KeyCode::Delete,
+                modifiers: KeyModifiers::NONE,
+            } => self.writer.delete_char_forward(),
+            // delete words - terminals send Ctrl-H or Alt-Backspace for Ctrl-Backspace
+            KeyEvent {
+                code: KeyCode::Backspace,
+                modifiers: KeyModifiers::CONTROL | KeyModifiers::ALT,
+            }
+            | KeyEvent {
+                code: KeyCode::Char('h'),
+                modifiers: KeyModifiers::CONTROL,
+            } => self.writer.delete_word(false),
+            KeyEvent {
+                code: KeyCode::Delete,
+                modifiers: KeyModifiers::CONTROL,
+            } => self.writer.delete_word(true),
+            // cut, copy and paste
+            KeyEvent {
+                code: key @ KeyCode::Char('x' | 'c'),
+                modifiers: KeyModifiers::CONTROL,
+            } => self.writer.copy(key == KeyCode::Char('x')),
+            KeyEvent {
+                code: KeyCode::Char('v'),
+                modifiers: KeyModifiers::CONTROL,
+            } => self.writer.paste(),
+            // drop the selection and the other cursors
+            KeyEvent {
+                code: KeyCode::Esc,
                 modifiers: KeyModifiers::NONE,
             } => {
-                if matches!(key, KeyCode::Delete) {
-                    self.writer.move_cursor(KeyCode::Right);
-                }
-                self.writer.delete_char();
+                self.writer.select(false);
+                self.writer.single_cursor();
             }
             // insert new line
             KeyEvent {
                 code: KeyCode::Enter,
                 modifiers: KeyModifiers::NONE,
             } => {
-                self.writer.insert_newline();
+                if self.writer.showing_grep {
+                    self.open_grep_match()?;
+                } else {
+                    self.writer.insert_newline();
+                }
             }
             // insert char
             KeyEvent {
@@ -144,9 +344,330 @@ impl Editor {
         Ok(true)
     }
 
+    /// execute command
+    fn execute_command(&mut self, command: &str) -> crossterm::Result<()> {
+        let (name, argument) = command
+            .trim()
+            .split_once(' ')
+            .map(|(name, argument)| (name, argument.trim()))
+            .unwrap_or((command.trim(), ""));
+        match name {
+            "lf" => self.writer.set_line_ending(LineEnding::Lf),
+            "crlf" => self.writer.set_line_ending(LineEnding::CrLf),
+            "backup" => {
+                self.writer.backup = !self.writer.backup;
+                self.writer.status_message.set_message(format!(
+                    "Backup on save {}",
+                    if self.writer.backup { "on" } else { "off" }
+                ));
+            }
+            "largefile" if argument.is_empty() => self.writer.status_message.set_message(format!(
+                "Large-file mode from {}",
+                file::format_size(self.writer.large_file_size)
+            )),
+            "largefile" => match file::parse_size(argument) {
+                Some(size) if size > 0 => {
+                    self.writer.large_file_size = size;
+                    self.writer.status_message.set_message(format!(
+                        "Large-file mode from {} for the files opened next",
+                        file::format_size(size)
+                    ));
+                }
+                _ => self
+                    .writer
+                    .status_message
+                    .set_message(format!("Invalid size: {}", argument)),
+            },
+            // a file that can't be written stays read-only, saving it would replace it anyway
+            "readonly"
+                if self.writer.editor_rows.read_only
+                    && self
+                        .writer
+                        .editor_rows
+                        .filename
+                        .as_deref()
+                        .is_some_and(|it| !file::is_writable(it)) =>
+            {
+                self.writer
+                    .status_message
+                    .set_message("The file or its directory is not writable".into())
+            }
+            "readonly" => {
+                self.writer.editor_rows.read_only = !self.writer.editor_rows.read_only;
+                self.writer.status_message.set_message(format!(
+                    "Read-only {}",
+                    if self.writer.editor_rows.read_only {
+                        "on"
+                    } else {
+                        "off"
+                    }
+                ));
+            }
+            "reload" => {
+                if !self.writer.is_dirty()
+                    || self.writer.choose(
+                        "Discard unsaved changes and reload the file? (y/n)",
+                        &['y', 'n'],
+                    )? == Some('y')
+                {
+                    self.writer.reload();
+                }
+            }
+            "encoding" | "reopen" => match Encoding::from_name(argument) {
+                Some(encoding) if name == "encoding" => self.writer.set_encoding(encoding),
+                Some(encoding) => {
+                    if !self.writer.is_dirty()
+                        || self.writer.choose(
+                            "Discard unsaved changes and reopen the file? (y/n)",
+                            &['y', 'n'],
+                        )? == Some('y')
+                    {
+                        self.writer.reopen(Some(encoding));
+                    }
+                }
+                None => self.writer.status_message.set_message(format!(
+                    "Unknown encoding: {} (utf-8, utf-8-bom, utf-16le, utf-16be, latin1)",
+                    argument
+                )),
+            },
+            "grep" if argument.is_empty() => self
+                .writer
+                .status_message
+                .set_message("Usage: grep <pattern>".into()),
+            "grep" => {
+                if let Some(grep) = self.writer.grep(argument) {
+                    let previous = self.writer.grep.replace(grep);
+                    self.writer.show_grep()?;
+                    if !self.writer.showing_grep {
+                        self.writer.grep = previous;
+                    }
+                }
+            }
+            "marks" => self.writer.list_marks(),
+            "results" => match self.writer.grep {
+                None => self
+                    .writer
+                    .status_message
+                    .set_message("No grep results".into()),
+                Some(_) => self.writer.show_grep()?,
+            },
+            _ => self
+                .writer
+                .status_message
+                .set_message(format!("Unknown command: {}", command)),
+        }
+        Ok(())
+    }
+
+    /// open the file, asking first if it looks binary, and offer to recover its swap file
+    pub fn open(&mut self, file: PathBuf) -> crossterm::Result<bool> {
+        if file::looks_binary(&file).unwrap_or(false) {
+            let question = format!(
+                "\"{}\" looks like a binary file. Open it anyway? (y/n)",
+                file.display()
+            );
+            if self.writer.choose(&question, &['y', 'n'])? != Some('y') {
+                self.writer
+                    .status_message
+                    .set_message("Open Aborted".into());
+                return Ok(false);
+            }
+        }
+        if !self.writer.open(file)? {
+            return Ok(false);
+        }
+        self.check_swap_file()?;
+        Ok(true)
+    }
+
+    /// open the file of the grep result under the cursor at the match
+    fn open_grep_match(&mut self) -> crossterm::Result<()> {
+        let y = self.writer.cursor_controller.cursor_y;
+        let Some(grep) = self.writer.grep.as_mut() else {
+            return Ok(());
+        };
+        let Some(found) = grep.matches.get(y) else {
+            return Ok(());
+        };
+        grep.selected = y;
+        let (path, line, column) = (found.path.clone(), found.line, found.column);
+        if self.open(path)? {
+            self.writer.go_to(line, column);
+            self.writer.center_view();
+        }
+        Ok(())
+    }
+
+    /// open the file given on the command line - `file`, `file:line[:col]` or `+line file`
+    pub fn open_args(&mut self, args: &[String]) -> crossterm::Result<()> {
+        let (file, location) = match args {
+            [line, file, ..] if line.starts_with('+') => {
+                (PathBuf::from(file), parse_location(&line[1..]))
+            }
+            [file, ..] if PathBuf::from(file).exists() => (file.into(), None),
+            [file, ..] => split_location(file),
+            [] => return Ok(()),
+        };
+        if self.open(file)? {
+            if let Some((line, column)) = location {
+                self.writer.go_to(line, column);
+                self.writer.center_view();
+            }
+        }
+        Ok(())
+    }
+
+    /// offer to recover a swap file left newer than the file by a previous session
+    fn check_swap_file(&mut self) -> crossterm::Result<()> {
+        let swap_path = match &self.writer.editor_rows.filename {
+            Some(filename) => SwapFile::find(filename),
+            None => None,
+        };
+        let Some(swap_path) = swap_path else {
+            return Ok(());
+        };
+        let swapped = match fs::read_to_string(&swap_path) {
+            Ok(swapped) => swapped,
+            Err(err) => {
+                self.writer
+                    .status_message
+                    .set_message(format!("Can't read swap file! I/O error: {}", err));
+                return Ok(());
+            }
+        };
+        let mut message = String::from("Found a swap file newer than the file");
+        loop {
+            let question = format!(
+                "{}. (r)ecover, (d)iff or (x) discard it? (Esc to ignore)",
+                message
+            );
+            match self.writer.choose(&question, &['r', 'd', 'x'])? {
+                Some('r') => {
+                    self.writer.replace_contents(swapped);
+                    self.writer.swap_file.adopt(swap_path);
+                    self.writer
+                        .status_message
+                        .set_message("Recovered from swap file".into());
+                }
+                Some('d') => {
+                    message = swap::diff_summary(&self.writer.editor_rows, &swapped);
+                    self.writer.preview = swap::diff_rows(&self.writer.editor_rows, &swapped);
+                    continue;
+                }
+                Some('x') => {
+                    let _ = fs::remove_file(&swap_path);
+                    self.writer
+                        .status_message
+                        .set_message("Swap file discarded".into());
+                }
+                _ => {}
+            }
+            self.writer.preview.clear();
+            return Ok(());
+        }
+    }
+
     /// run the editor
     pub fn run(&mut self) -> crossterm::Result<bool> {
         self.writer.refresh_screen()?;
-        self.process_keypress()
+        // while no key is pressed, keep the swap file and the file on disk in check
+        let key = loop {
+            match self.reader.read_key()? {
+                Some(key) => break key,
+                None => {
+                    if self.writer.idle() {
+                        self.writer.refresh_screen()?;
+                    }
+                }
+            }
+        };
+        let running = self.process_keypress(key)?;
+        if running {
+            self.writer.check_disk();
+            self.writer.update_swap_file();
+        } else {
+            self.writer.swap_file.remove();
+        }
+        Ok(running)
+    }
+}
+
+/// parse `line[:col]`, both counted from 1, into a row and a char column counted from 0
+fn parse_location(location: &str) -> Option<(usize, usize)> {
+    let (line, column) = match location.trim().split_once(':') {
+        Some((line, column)) => (line, Some(column)),
+        None => (location.trim(), None),
+    };
+    let line = line.parse::<usize>().ok()?;
+    let column = match column {
+        Some(column) => column.parse::<usize>().ok()?,
+        None => 1,
+    };
+    Some((line.saturating_sub(1), column.saturating_sub(1)))
+}
+
+/// split `file:line[:col]`, as compilers print locations, into the file and the location
+fn split_location(arg: &str) -> (PathBuf, Option<(usize, usize)>) {
+    let mut parts = arg.rsplitn(3, ':');
+    let last = parts.next().unwrap_or_default();
+    let middle = parts.next();
+    let first = parts.next();
+    let is_number = |part: &str| !part.is_empty() && part.bytes().all(|it| it.is_ascii_digit());
+    match (first, middle) {
+        (Some(file), Some(line)) if is_number(line) && is_number(last) => {
+            (file.into(), parse_location(&format!("{}:{}", line, last)))
+        }
+        (_, Some(file)) if is_number(last) => {
+            let file = match first {
+                Some(first) => format!("{}:{}", first, file),
+                None => file.into(),
+            };
+            (file.into(), parse_location(last))
+        }
+        _ => (arg.into(), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_location_counts_from_zero() {
+        assert_eq!(parse_location("12"), Some((11, 0)));
+        assert_eq!(parse_location(" 12:5 "), Some((11, 4)));
+        assert_eq!(parse_location("0:0"), Some((0, 0)));
+    }
+
+    #[test]
+    fn parse_location_rejects_what_is_not_a_number() {
+        assert_eq!(parse_location(""), None);
+        assert_eq!(parse_location("x"), None);
+        assert_eq!(parse_location("3:"), None);
+        assert_eq!(parse_location("-3"), None);
+    }
+
+    #[test]
+    fn split_location_takes_the_line_and_column() {
+        assert_eq!(
+            split_location("src/main.rs:10:4"),
+            ("src/main.rs".into(), Some((9, 3)))
+        );
+        assert_eq!(
+            split_location("src/main.rs:10"),
+            ("src/main.rs".into(), Some((9, 0)))
+        );
+    }
+
+    #[test]
+    fn split_location_keeps_other_colons_in_the_file() {
+        assert_eq!(split_location("a:b:3"), ("a:b".into(), Some((2, 0))));
+        assert_eq!(
+            split_location("c:\\x.rs:3:1"),
+            ("c:\\x.rs".into(), Some((2, 0)))
+        );
+        assert_eq!(split_location("notes:todo"), ("notes:todo".into(), None));
+        assert_eq!(split_location("file:"), ("file:".into(), None));
+        assert_eq!(split_location("42"), ("42".into(), None));
     }
 }
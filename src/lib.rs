@@ -8,6 +8,13 @@ use crossterm::terminal;
 const VERSION: &str = "0.1.0";
 const TAB_SIZE: usize = 4;
 const QUIT_TIMES: u8 = 2;
+const MAKE_BACKUP: bool = false;
+const SWAP_INTERVAL: u64 = 4;
+const LARGE_FILE_SIZE: u64 = 64 << 20;
+const LARGE_FILE_SIZE_VAR: &str = "RIM_LARGE_FILE_SIZE";
+const BRACKET_SCAN_ROWS: usize = 5000;
+const INDENT_SCAN_ROWS: usize = 1000;
+const OSC52_LIMIT: usize = 1 << 20;
 
 const BACKGROUND_COLOR: (u8, u8, u8) = (48, 56, 69);
 const DEFAULT_COLOR: (u8, u8, u8) = (195, 211, 222);
@@ -15,6 +22,10 @@ const NUMBER_COLOR: (u8, u8, u8) = (209, 154, 102);
 const CHAR_STRING_COLOR: (u8, u8, u8) = (146, 214, 158);
 const COMMENT_COLOR: (u8, u8, u8) = (89, 98, 111);
 const KEYWORDS_COLOR: (u8, u8, u8) = (199, 146, 234);
+const MATCH_COLOR: (u8, u8, u8) = (229, 192, 123);
+const BRACKET_COLOR: (u8, u8, u8) = (92, 99, 112);
+const SELECTION_COLOR: (u8, u8, u8) = (62, 81, 122);
+const CURSOR_COLOR: (u8, u8, u8) = (171, 178, 191);
 
 pub struct CleanUp;
 
@@ -1,11 +1,13 @@
 use crossterm::terminal;
 use rim::editor::Editor;
 use rim::CleanUp;
+use std::env;
 
 fn main() -> crossterm::Result<()> {
     let _clean_up = CleanUp;
     terminal::enable_raw_mode()?;
     let mut editor = Editor::new();
+    editor.open_args(&env::args().skip(1).collect::<Vec<_>>())?;
     while editor.run()? {}
     Ok(())
 }
@@ -5,13 +5,21 @@ use std::time::Duration;
 pub struct Reader;
 
 impl Reader {
-    /// read key presses
-    pub fn read_key(&self) -> crossterm::Result<KeyEvent> {
+    /// read a key press, None if there was none within half a second
+    pub fn read_key(&self) -> crossterm::Result<Option<KeyEvent>> {
+        if event::poll(Duration::from_millis(500))? {
+            if let Event::Key(event) = event::read()? {
+                return Ok(Some(event));
+            }
+        }
+        Ok(None)
+    }
+
+    /// wait for a key press
+    pub fn wait_key(&self) -> crossterm::Result<KeyEvent> {
         loop {
-            if event::poll(Duration::from_millis(500))? {
-                if let Event::Key(event) = event::read()? {
-                    return Ok(event);
-                }
+            if let Some(event) = self.read_key()? {
+                return Ok(event);
             }
         }
     }
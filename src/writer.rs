@@ -1,47 +1,549 @@
+pub mod block;
+pub mod buffer;
+pub mod clipboard;
 pub mod content;
 pub mod cursor_controller;
+pub mod encoding;
+pub mod file;
+pub mod finder;
+pub mod grep;
 pub mod highlight;
+pub mod history;
+pub mod marks;
+pub mod outline;
+pub mod picker;
+pub mod search;
 pub mod status;
+pub mod swap;
 
-use crate::writer::content::{EditorContents, EditorRows};
+use crate::prompt;
+use crate::reader::Reader;
+use crate::writer::block::Block;
+use crate::writer::clipboard::{Clipboard, Shape};
+use crate::writer::content::{
+    grapheme_width, truncate_width, word_at, EditorContents, EditorRows, LineEnding, Row,
+};
 use crate::writer::cursor_controller::CursorController;
-use crate::writer::highlight::{RustHighlight, SyntaxHighlight};
+use crate::writer::encoding::Encoding;
+use crate::writer::file::DiskState;
+use crate::writer::grep::Grep;
+use crate::writer::highlight::{
+    HighlightType, Indent, PlainHighlight, RustHighlight, SyntaxHighlight,
+};
+use crate::writer::history::Change;
+use crate::writer::outline::{Outline, Symbol};
+use crate::writer::picker::Picker;
+use crate::writer::search::{Case, Matcher, Search, SearchOptions};
 use crate::writer::status::StatusMessage;
-use crate::{BACKGROUND_COLOR, VERSION};
-use crossterm::event::KeyCode;
+use crate::writer::swap::SwapFile;
+use crate::{
+    BACKGROUND_COLOR, BRACKET_SCAN_ROWS, INDENT_SCAN_ROWS, LARGE_FILE_SIZE, LARGE_FILE_SIZE_VAR,
+    MAKE_BACKUP, VERSION,
+};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use crossterm::style::{Color, SetBackgroundColor};
 use crossterm::terminal::ClearType;
 use crossterm::{cursor, execute, queue, style, terminal};
-use std::cmp;
 use std::io::{stdout, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::{cmp, env, iter, mem};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 /// Writer struct
 pub struct Writer {
     pub win_size: (usize, usize),
     pub editor_contents: EditorContents,
     pub cursor_controller: CursorController,
+    pub cursors: Vec<CursorController>,
+    pub block: Option<Block>,
     pub editor_rows: EditorRows,
     pub status_message: StatusMessage,
-    pub dirty: u64,
+    pub backup: bool,
+    pub large_file_size: u64,
+    pub swap_file: SwapFile,
+    pub checked_disk_state: Option<DiskState>,
+    pub search: Option<Search>,
+    pub search_options: SearchOptions,
+    pub grep: Option<Grep>,
+    pub showing_grep: bool,
+    pub matching_bracket: Option<(usize, usize)>,
+    pub outline: Option<Outline>,
+    pub picker: Option<Picker>,
+    pub clipboard: Option<Clipboard>,
+    pub preview: Vec<String>,
+    edit_depth: usize,
     pub syntax_highlight: Option<Box<dyn SyntaxHighlight>>,
 }
 
+impl Default for Writer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Writer {
     /// constructor
     pub fn new() -> Self {
         let win_size = terminal::size()
             .map(|(x, y)| (x as usize, y as usize - 2))
             .unwrap();
-        let mut syntax_highlight = None;
         Self {
             win_size,
             editor_contents: EditorContents::new(),
             cursor_controller: CursorController::new(win_size),
-            editor_rows: EditorRows::new(&mut syntax_highlight),
-            status_message: StatusMessage::new("HELP: Ctrl-S = Save | Ctrl-Q = Quit".into()),
-            dirty: 0,
-            syntax_highlight,
+            cursors: Vec::new(),
+            block: None,
+            editor_rows: EditorRows::new(),
+            status_message: StatusMessage::new(
+                "HELP: Ctrl-S = Save | Ctrl-Q = Quit | Ctrl-F = Find | Ctrl-Z = Undo | Ctrl-E = Command".into(),
+            ),
+            backup: MAKE_BACKUP,
+            large_file_size: env::var(LARGE_FILE_SIZE_VAR)
+                .ok()
+                .and_then(|size| file::parse_size(&size))
+                .unwrap_or(LARGE_FILE_SIZE),
+            swap_file: SwapFile::new(),
+            checked_disk_state: None,
+            search: None,
+            search_options: SearchOptions::default(),
+            grep: None,
+            showing_grep: false,
+            matching_bracket: None,
+            outline: None,
+            picker: None,
+            clipboard: None,
+            preview: Vec::new(),
+            edit_depth: 0,
+            syntax_highlight: None,
+        }
+    }
+
+    /// open the file in place of the buffer, which is given up only once the file is read,
+    /// whether it was
+    pub fn open(&mut self, file: PathBuf) -> crossterm::Result<bool> {
+        let mut syntax_highlight = None;
+        match EditorRows::from_file(file, &mut syntax_highlight, self.large_file_size) {
+            Ok(editor_rows) => {
+                if !self.close_buffer()? {
+                    return Ok(false);
+                }
+                self.editor_rows = editor_rows;
+                self.syntax_highlight = syntax_highlight;
+                self.outline = None;
+                self.showing_grep = false;
+                self.cursor_controller.anchor = None;
+                self.cursors.clear();
+                self.block = None;
+                self.set_cursor((0, 0));
+                self.checked_disk_state = None;
+                if self.editor_rows.large {
+                    self.status_message.set_message(format!(
+                        "WARNING!! Large file: syntax highlighting off, rows read as needed{}",
+                        if self.editor_rows.read_only {
+                            ", read-only"
+                        } else {
+                            ""
+                        }
+                    ));
+                } else if self.editor_rows.read_only {
+                    self.status_message
+                        .set_message("File is not writable, opened read-only".into());
+                }
+                Ok(true)
+            }
+            Err(err) => {
+                self.status_message.set_message(err.to_string());
+                Ok(false)
+            }
+        }
+    }
+
+    /// give up the buffer for another one, asking first if it has unsaved changes
+    pub fn close_buffer(&mut self) -> crossterm::Result<bool> {
+        if self.is_dirty()
+            && self.choose("Discard unsaved changes? (y/n)", &['y', 'n'])? != Some('y')
+        {
+            return Ok(false);
+        }
+        self.swap_file.remove();
+        Ok(true)
+    }
+
+    /// search the files under the working directory
+    pub fn grep(&mut self, pattern: &str) -> Option<Grep> {
+        let matcher = match Matcher::new(pattern, &self.search_options) {
+            Ok(matcher) => matcher,
+            Err(err) => {
+                self.status_message
+                    .set_message(format!("Invalid pattern: {}", err));
+                return None;
+            }
+        };
+        self.status_message
+            .set_message(format!("Searching for {}...", pattern));
+        let _ = self.refresh_screen();
+        let grep = Grep::run(
+            Path::new("."),
+            pattern,
+            &matcher,
+            &PlainHighlight::new(),
+            self.large_file_size,
+        );
+        if grep.matches.is_empty() {
+            self.status_message
+                .set_message(format!("No matches for {}", pattern));
+            return None;
+        }
+        Some(grep)
+    }
+
+    /// show the grep results in a read-only buffer in place of the buffer,
+    /// asking first if it has unsaved changes
+    pub fn show_grep(&mut self) -> crossterm::Result<()> {
+        if self.grep.is_none() || !self.showing_grep && !self.close_buffer()? {
+            return Ok(());
         }
+        let Some(grep) = self.grep.as_ref() else {
+            return Ok(());
+        };
+        let rows: Vec<_> = grep.matches.iter().map(|it| it.row()).collect();
+        self.editor_rows = EditorRows::from_rows(rows.iter().map(String::as_str));
+        self.editor_rows.read_only = true;
+        self.syntax_highlight = None;
+        self.showing_grep = true;
+        self.checked_disk_state = None;
+        self.cursor_controller.anchor = None;
+        self.cursors.clear();
+        self.block = None;
+        let message = format!(
+            "{} matches in {} files. Press Enter to open one, the results command to come back",
+            grep.matches.len(),
+            grep.files
+        );
+        self.set_cursor((0, grep.selected));
+        self.status_message.set_message(message);
+        Ok(())
+    }
+
+    /// move the cursor to the char column of the row, reading a large file down to it
+    pub fn go_to(&mut self, y: usize, column: usize) {
+        self.single_cursor();
+        if let Err(err) = self.editor_rows.load_rows(y) {
+            self.status_message
+                .set_message(format!("Can't read the file! I/O error: {}", err));
+        }
+        let number_of_rows = self.editor_rows.number_of_rows();
+        if number_of_rows == 0 {
+            self.set_cursor((0, 0));
+            return;
+        }
+        let y = cmp::min(y, number_of_rows - 1);
+        let start = self.editor_rows.row_to_char(y);
+        let row_chars = self.editor_rows.row_content(y).chars().count();
+        let cursor = self
+            .editor_rows
+            .cursor_position(start + cmp::min(column, row_chars));
+        self.set_cursor(cursor);
+    }
+
+    /// char index of the cursor
+    fn cursor_char(&self) -> usize {
+        let (x, y) = self.cursor();
+        self.editor_rows.char_index(x, y)
+    }
+
+    /// remember the cursor position in the jump list before a big move
+    pub fn record_jump(&mut self) {
+        let at = self.cursor_char();
+        self.editor_rows.marks.push_jump(at);
+    }
+
+    /// go back or forward in the jump list
+    pub fn jump(&mut self, back: bool) {
+        self.single_cursor();
+        let at = self.cursor_char();
+        let marks = &mut self.editor_rows.marks;
+        let to = if back {
+            marks.back(at)
+        } else {
+            marks.forward()
+        };
+        match to {
+            Some(to) => {
+                let to = cmp::min(to, self.editor_rows.len_chars());
+                self.set_cursor(self.editor_rows.cursor_position(to));
+            }
+            None => self.status_message.set_message(format!(
+                "Already at the {} jump",
+                if back { "oldest" } else { "newest" }
+            )),
+        }
+    }
+
+    /// set the named mark at the cursor
+    pub fn set_mark(&mut self, name: char) {
+        let at = self.cursor_char();
+        self.editor_rows.marks.set(name, at);
+        self.status_message
+            .set_message(format!("Mark {} set", name));
+    }
+
+    /// move the cursor to the named mark
+    pub fn go_to_mark(&mut self, name: char) {
+        self.single_cursor();
+        match self.editor_rows.marks.get(name) {
+            Some(at) => {
+                self.record_jump();
+                let at = cmp::min(at, self.editor_rows.len_chars());
+                self.set_cursor(self.editor_rows.cursor_position(at));
+                self.center_view();
+            }
+            None => self
+                .status_message
+                .set_message(format!("Mark {} not set", name)),
+        }
+    }
+
+    /// list the named marks with their line and column
+    pub fn list_marks(&mut self) {
+        let marks: Vec<_> = self
+            .editor_rows
+            .marks
+            .list()
+            .map(|(name, at)| {
+                let at = cmp::min(at, self.editor_rows.len_chars());
+                let (x, y) = self.editor_rows.cursor_position(at);
+                format!("{} {}:{}", name, y + 1, x + 1)
+            })
+            .collect();
+        self.status_message.set_message(if marks.is_empty() {
+            "No marks set".into()
+        } else {
+            format!("Marks: {}", marks.join(" | "))
+        });
+    }
+
+    /// scroll so that the cursor row is in the middle of the screen
+    pub fn center_view(&mut self) {
+        self.cursor_controller.row_offset = self
+            .cursor_controller
+            .cursor_y
+            .saturating_sub(self.win_size.1 / 2);
+    }
+
+    /// the bracket matching the one under the cursor, brackets in strings and comments aside
+    pub fn find_matching_bracket(&self) -> Option<(usize, usize)> {
+        let (x, y) = self.cursor();
+        if y >= self.editor_rows.number_of_rows() {
+            return None;
+        }
+        let row = self.highlighted_row(y);
+        let (_, bracket, highlight) = *row.grapheme_highlights().get(x)?;
+        let (open, close, forward) = match bracket {
+            "(" => ("(", ")", true),
+            "[" => ("[", "]", true),
+            "{" => ("{", "}", true),
+            ")" => (")", "(", false),
+            "]" => ("]", "[", false),
+            "}" => ("}", "{", false),
+            _ => return None,
+        };
+        if !highlight.is_code() {
+            return None;
+        }
+        let mut depth = 0;
+        let rows: Box<dyn Iterator<Item = usize>> = if forward {
+            Box::new(y..cmp::min(y + BRACKET_SCAN_ROWS, self.editor_rows.number_of_rows()))
+        } else {
+            Box::new((y.saturating_sub(BRACKET_SCAN_ROWS)..=y).rev())
+        };
+        for at in rows {
+            let row = self.highlighted_row(at);
+            let mut graphemes: Vec<_> = row.grapheme_highlights().into_iter().enumerate().collect();
+            if at == y {
+                graphemes = if forward {
+                    graphemes.split_off(x)
+                } else {
+                    graphemes.truncate(x + 1);
+                    graphemes
+                };
+            }
+            if !forward {
+                graphemes.reverse();
+            }
+            for (gx, (_, grapheme, highlight)) in graphemes {
+                if !highlight.is_code() {
+                    continue;
+                }
+                if grapheme == open {
+                    depth += 1;
+                } else if grapheme == close {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some((gx, at));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// move the cursor to the bracket matching the one under it
+    pub fn jump_to_bracket(&mut self) {
+        self.single_cursor();
+        match self.find_matching_bracket() {
+            Some(cursor) => {
+                self.record_jump();
+                self.set_cursor(cursor);
+            }
+            None => self
+                .status_message
+                .set_message("No matching bracket".into()),
+        }
+    }
+
+    /// scan the buffer for symbols again if it changed since the last scan,
+    /// whether the outline changed
+    pub fn update_outline(&mut self) -> bool {
+        let changes = self.editor_rows.history.changes();
+        match self.syntax_highlight.as_deref() {
+            Some(syntax) if !syntax.symbol_keywords().is_empty() => {
+                if self.outline.as_ref().is_none_or(|it| it.changes != changes) {
+                    self.outline = Some(Outline::scan(&self.editor_rows, syntax));
+                    return true;
+                }
+                false
+            }
+            _ => self.outline.take().is_some(),
+        }
+    }
+
+    /// pick a symbol of the buffer in a filtered list and jump to it
+    pub fn go_to_symbol(&mut self) -> crossterm::Result<()> {
+        self.update_outline();
+        let Some(outline) = self.outline.as_ref().filter(|it| !it.symbols.is_empty()) else {
+            self.status_message.set_message("No symbols".into());
+            return Ok(());
+        };
+        self.picker = Some(Picker::new(
+            outline.symbols.iter().map(Symbol::label).collect(),
+        ));
+        let input = prompt!(
+            *self,
+            "Go to symbol: {} (Up/Down to select, Esc to cancel)",
+            callback = Writer::picker_callback,
+            allow_empty = true
+        );
+        let picked = self.picker.take().and_then(|it| it.picked());
+        let symbol = picked.and_then(|it| self.outline.as_ref()?.symbols.get(it));
+        if let (Some(_), Some(symbol)) = (input, symbol) {
+            let (y, x) = (symbol.y, symbol.x);
+            self.record_jump();
+            self.go_to(y, x);
+            self.center_view();
+        }
+        Ok(())
+    }
+
+    /// pick one of the files under the working directory in a fuzzy filtered list
+    pub fn pick_file(&mut self) -> crossterm::Result<Option<PathBuf>> {
+        self.status_message.set_message("Listing files...".into());
+        self.refresh_screen()?;
+        let files = finder::project_files(Path::new("."));
+        if files.is_empty() {
+            self.status_message.set_message("No files".into());
+            return Ok(None);
+        }
+        self.picker = Some(Picker::new(
+            files.iter().map(|it| it.display().to_string()).collect(),
+        ));
+        let input = prompt!(
+            *self,
+            "Open file: {} (Up/Down to select, Esc to cancel)",
+            callback = Writer::picker_callback,
+            allow_empty = true
+        );
+        let picked = self.picker.take().and_then(|it| it.picked());
+        Ok(input.and(picked).map(|it| files[it].clone()))
+    }
+
+    /// narrow the picker down as the input is typed, move through it with the arrows
+    fn picker_callback(&mut self, input: &str, key: KeyEvent) -> String {
+        let Some(picker) = self.picker.as_mut() else {
+            return String::new();
+        };
+        match key.code {
+            KeyCode::Up => picker.step(false),
+            KeyCode::Down => picker.step(true),
+            KeyCode::Null => picker.filter(input),
+            _ => {}
+        }
+        picker.counter()
+    }
+
+    /// get the at row, rendered and syntax highlighted
+    pub fn highlighted_row(&self, at: usize) -> Row {
+        let mut row = self.editor_rows.get_editor_row(at);
+        if let Some(it) = self.syntax_highlight.as_ref() {
+            it.highlight_row(&mut row, at > 0 && self.editor_rows.is_comment(at - 1));
+        }
+        row
+    }
+
+    /// get the at row, rendered and highlighted, search matches, brackets,
+    /// the selections and the other cursors included
+    pub fn get_row(&self, at: usize) -> Row {
+        let mut row = self.highlighted_row(at);
+        if let Some((x, _)) = self.matching_bracket.filter(|(_, y)| *y == at) {
+            let start = row
+                .grapheme_highlights()
+                .get(x)
+                .map_or(0, |(chars, _, _)| *chars);
+            row.highlight_chars(start..start + 1, HighlightType::Bracket);
+        }
+        let start = self.editor_rows.row_to_char(at);
+        let end = start + row.row_content.chars().count();
+        let cursors = iter::once(&self.cursor_controller).chain(&self.cursors);
+        for selection in cursors.filter_map(|it| self.selection_of(it)) {
+            if selection.start < end && selection.end > start {
+                let chars =
+                    selection.start.saturating_sub(start)..cmp::min(selection.end, end) - start;
+                row.highlight_chars(chars, HighlightType::Selection);
+            }
+        }
+        if let Some(search) = self.search.as_ref() {
+            search.matches_in(start..end + 1).iter().for_each(|it| {
+                let chars = it.start.saturating_sub(start)..cmp::min(it.end, end) - start;
+                row.highlight_chars(chars, HighlightType::Match);
+            });
+        }
+        if let Some(block) = self.block.filter(|it| it.rows().contains(&at)) {
+            match row.column_chars(block.columns()) {
+                Some(chars) if !block.columns().is_empty() => {
+                    row.highlight_chars(chars, HighlightType::Selection)
+                }
+                // with no width, the block is drawn as a cursor on every row
+                Some(chars) if at != self.cursor_controller.cursor_y => {
+                    if chars.start < row.row_content.chars().count() {
+                        row.highlight_chars(chars.start..chars.start + 1, HighlightType::Cursor);
+                    } else {
+                        row.highlight_end(HighlightType::Cursor);
+                    }
+                }
+                _ => {}
+            }
+        }
+        for cursor in self.cursors.iter().filter(|it| it.cursor_y == at) {
+            let x = self.editor_rows.char_index(cursor.cursor_x, at) - start;
+            if x < end - start {
+                row.highlight_chars(x..x + 1, HighlightType::Cursor);
+            } else {
+                row.highlight_end(HighlightType::Cursor);
+            }
+        }
+        row
     }
 
     pub fn select_syntax(extension: &str) -> Option<Box<dyn SyntaxHighlight>> {
@@ -69,16 +571,40 @@ impl Writer {
             padding -= 1;
         }
         (0..padding).for_each(|_| self.editor_contents.push(' '));
-        self.editor_contents.push_str(&welcome);
+        self.editor_contents.push_str(welcome);
     }
 
     /// draw editor rows
     pub fn draw_rows(&mut self) {
         let screen_rows = self.win_size.1;
         let screen_columns = self.win_size.0;
+        // the picker or the preview shown over the bottom half of the screen
+        let picker_rows: Vec<(String, bool)> = match self.picker.as_ref() {
+            Some(picker) => picker
+                .visible(screen_rows / 2)
+                .into_iter()
+                .map(|(item, selected)| (item.into(), selected))
+                .collect(),
+            None => self
+                .preview
+                .iter()
+                .take(screen_rows / 2)
+                .map(|row| (row.clone(), false))
+                .collect(),
+        };
+        let picker_start = screen_rows - picker_rows.len();
         for i in 0..screen_rows {
             let file_row = i + self.cursor_controller.row_offset;
-            if file_row >= self.editor_rows.number_of_rows() {
+            if let Some((item, selected)) = i.checked_sub(picker_start).map(|it| &picker_rows[it]) {
+                let item = format!("{}{}", if *selected { "> " } else { "  " }, item);
+                let item = truncate_width(&item, screen_columns);
+                self.editor_contents
+                    .push_str(&style::Attribute::Reverse.to_string());
+                self.editor_contents.push_str(item);
+                (item.width()..screen_columns).for_each(|_| self.editor_contents.push(' '));
+                self.editor_contents
+                    .push_str(&style::Attribute::Reset.to_string());
+            } else if file_row >= self.editor_rows.number_of_rows() {
                 if self.editor_rows.number_of_rows() == 0 && i == screen_rows / 3 {
                     let mut welcome = format!("RIM Editor --- Version {}", VERSION);
                     self.draw_welcome(screen_columns, &mut welcome);
@@ -86,21 +612,20 @@ impl Writer {
                     self.editor_contents.push('~');
                 }
             } else {
-                let row = self.editor_rows.get_editor_row(file_row);
-                let render = &row.render;
-                let column_offset = self.cursor_controller.column_offset;
-                let len = cmp::min(render.len().saturating_sub(column_offset), screen_columns);
-                let start = if len == 0 { 0 } else { column_offset };
-                self.syntax_highlight
-                    .as_ref()
-                    .map(|syntax_highlight| {
-                        syntax_highlight.color_row(
-                            &render[start..start + len],
-                            &row.highlight[start..start + len],
-                            &mut self.editor_contents,
-                        )
-                    })
-                    .unwrap_or_else(|| self.editor_contents.push_str(&render[start..start + len]));
+                let row = self.get_row(file_row);
+                let (padding, range) =
+                    row.render_range(self.cursor_controller.column_offset, screen_columns);
+                (0..padding).for_each(|_| self.editor_contents.push(' '));
+                let render = &row.render[range.clone()];
+                if row.highlight.is_empty() {
+                    self.editor_contents.push_str(render);
+                } else {
+                    let plain = PlainHighlight::new();
+                    self.syntax_highlight
+                        .as_deref()
+                        .unwrap_or(&plain)
+                        .color_row(render, &row.highlight[range], &mut self.editor_contents);
+                }
             }
             queue!(
                 self.editor_contents,
@@ -116,29 +641,64 @@ impl Writer {
         self.editor_contents
             .push_str(&style::Attribute::Reverse.to_string());
         let info = format!(
-            "{} {} - {} lines",
-            self.editor_rows
-                .filename
+            "{}{}{} {} - {}{} lines{}{}{}",
+            match self.grep.as_ref() {
+                Some(grep) if self.showing_grep => format!("[grep {}]", grep.pattern),
+                _ => self
+                    .editor_rows
+                    .filename
+                    .as_ref()
+                    .and_then(|path| path.file_name())
+                    .and_then(|name| name.to_str())
+                    .unwrap_or("[No Name]")
+                    .into(),
+            },
+            if self.editor_rows.read_only {
+                " [read-only]"
+            } else {
+                ""
+            },
+            if self.editor_rows.large {
+                " [large]"
+            } else {
+                ""
+            },
+            if self.is_dirty() { "(modified)" } else { "" },
+            self.editor_rows.number_of_rows(),
+            if self.editor_rows.is_loading() {
+                "+"
+            } else {
+                ""
+            },
+            match self.cursors.len() {
+                0 => String::new(),
+                extra => format!(" | {} cursors", extra + 1),
+            },
+            self.block
+                .map(|it| format!(" | block {}x{}", it.rows().count(), it.columns().len()))
+                .unwrap_or_default(),
+            self.outline
                 .as_ref()
-                .and_then(|path| path.file_name())
-                .and_then(|name| name.to_str())
-                .unwrap_or("[No Name]"),
-            if self.dirty > 0 { "(modified)" } else { "" },
-            self.editor_rows.number_of_rows()
+                .and_then(|it| it.enclosing_function(self.cursor_controller.cursor_y))
+                .map(|it| format!(" | fn {}", it.name))
+                .unwrap_or_default()
         );
-        let info_len = cmp::min(info.len(), self.win_size.0);
+        let info = truncate_width(&info, self.win_size.0);
+        let info_len = info.width();
         let line_info = format!(
-            "{} | {}/{}",
+            "{} | {} | {} | {}/{}",
             self.syntax_highlight
                 .as_ref()
                 .map(|highlight| highlight.file_type())
                 .unwrap_or("no ft"),
+            self.editor_rows.encoding.name(),
+            self.editor_rows.line_ending.name(),
             self.cursor_controller.cursor_y + 1,
             self.editor_rows.number_of_rows()
         );
-        self.editor_contents.push_str(&info[..info_len]);
+        self.editor_contents.push_str(info);
         for i in info_len..self.win_size.0 {
-            if self.win_size.0 - i == line_info.len() {
+            if self.win_size.0 - i == line_info.width() {
                 self.editor_contents.push_str(&line_info);
                 break;
             } else {
@@ -159,7 +719,7 @@ impl Writer {
         .unwrap();
         if let Some(msg) = self.status_message.message() {
             self.editor_contents
-                .push_str(&msg[..cmp::min(self.win_size.0, msg.len())]);
+                .push_str(truncate_width(msg, self.win_size.0));
         }
     }
 
@@ -167,94 +727,1337 @@ impl Writer {
     pub fn move_cursor(&mut self, direction: KeyCode) {
         self.cursor_controller
             .move_cursor(direction, &self.editor_rows);
+        self.cursors
+            .iter_mut()
+            .for_each(|it| it.move_cursor(direction, &self.editor_rows));
+        self.merge_cursors();
     }
 
-    /// insert char
-    pub fn insert_char(&mut self, ch: char) {
-        if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
-            self.editor_rows
-                .insert_row(self.editor_rows.number_of_rows(), String::new());
-            self.dirty += 1;
+    /// move cursor by words, paragraphs or to either end of the buffer,
+    /// the ends are kept in the jump list, the rest of a large file is read
+    /// for the end only if wanted, the end of the rows read so far otherwise
+    pub fn move_cursor_far(&mut self, direction: KeyCode) -> crossterm::Result<()> {
+        if matches!(direction, KeyCode::Home | KeyCode::End) {
+            if direction == KeyCode::End
+                && self.editor_rows.is_loading()
+                && self.choose(
+                    "Read the rest of the large file to go to its end? (y/n)",
+                    &['y', 'n'],
+                )? == Some('y')
+            {
+                if let Err(err) = self.editor_rows.load_all() {
+                    self.status_message
+                        .set_message(format!("Can't read the file! I/O error: {}", err));
+                }
+            }
+            self.record_jump();
+        }
+        self.cursor_controller
+            .move_cursor_far(direction, &self.editor_rows);
+        self.cursors
+            .iter_mut()
+            .for_each(|it| it.move_cursor_far(direction, &self.editor_rows));
+        self.merge_cursors();
+        Ok(())
+    }
+
+    /// start selecting from the cursors when a motion is made with Shift, stop otherwise
+    pub fn select(&mut self, selecting: bool) {
+        self.block = None;
+        self.cursor_controller.select(selecting);
+        self.cursors.iter_mut().for_each(|it| it.select(selecting));
+    }
+
+    /// the chars between the selection anchor and the cursor
+    pub fn selection(&self) -> Option<Range<usize>> {
+        self.selection_of(&self.cursor_controller)
+    }
+
+    /// the chars between the selection anchor and the cursor of one of the cursors
+    fn selection_of(&self, cursor: &CursorController) -> Option<Range<usize>> {
+        let (x, y) = cursor.anchor?;
+        let anchor = self.editor_rows.char_index(x, y);
+        let at = self
+            .editor_rows
+            .char_index(cursor.cursor_x, cursor.cursor_y);
+        let (start, mut end) = (cmp::min(anchor, at), cmp::max(anchor, at));
+        // past the last row a selection from inside a row stops before its '\n',
+        // which would otherwise go and take the rest of that row with it
+        if end == self.editor_rows.len_chars() && self.editor_rows.cursor_position(start).0 > 0 {
+            end = self.editor_rows.last_row_end();
+        }
+        (start < end).then_some(start..end)
+    }
+
+    /// drop the cursors other than the primary one, and the block selection
+    pub fn single_cursor(&mut self) {
+        self.cursors.clear();
+        self.block = None;
+    }
+
+    /// start a block selection at the cursor, or move the corner of the block with the cursor
+    pub fn extend_block(&mut self, direction: KeyCode) {
+        let rows = self.editor_rows.number_of_rows();
+        let (_, y) = self.cursor();
+        if y >= rows {
+            return;
+        }
+        self.cursors.clear();
+        self.cursor_controller.anchor = None;
+        let mut block = self.block.unwrap_or_else(|| {
+            let row_content = self.editor_rows.row_content(y);
+            Block::new(self.cursor_controller.get_render_x(&row_content), y)
+        });
+        let (column, y) = block.corner;
+        block.corner = match direction {
+            KeyCode::Left | KeyCode::Right => {
+                let row = self.editor_rows.get_editor_row(y);
+                (row.column_step(column, direction == KeyCode::Right), y)
+            }
+            KeyCode::Up => (column, y.saturating_sub(1)),
+            KeyCode::Down => (column, cmp::min(y + 1, rows - 1)),
+            _ => block.corner,
+        };
+        self.set_block(block);
+    }
+
+    /// select the block, with the cursor at its corner
+    fn set_block(&mut self, block: Block) {
+        let (column, y) = block.corner;
+        let x = self.editor_rows.get_editor_row(y).get_row_content_x(column);
+        self.set_cursor((x, y));
+        self.block = Some(block);
+    }
+
+    /// the chars of the block on every row of it, None for the rows ending before it
+    fn block_chars(&self, block: Block) -> Vec<(usize, Option<Range<usize>>)> {
+        block
+            .rows()
+            .map(|y| {
+                let start = self.editor_rows.row_to_char(y);
+                let chars = self
+                    .editor_rows
+                    .get_editor_row(y)
+                    .column_chars(block.columns());
+                (y, chars.map(|it| start + it.start..start + it.end))
+            })
+            .collect()
+    }
+
+    /// the text of the block, a row of it per line
+    fn block_text(&self, block: Block) -> String {
+        self.block_chars(block)
+            .into_iter()
+            .map(|(_, chars)| {
+                chars
+                    .map(|it| self.editor_rows.text(it))
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// delete the text of the block within an open edit, leaving the block with no width
+    fn delete_block_text(&mut self, block: Block) {
+        for (_, chars) in self.block_chars(block).into_iter().rev() {
+            if let Some(chars) = chars.filter(|it| !it.is_empty()) {
+                self.apply(Change::Delete {
+                    at: chars.start,
+                    text: self.editor_rows.text(chars),
+                });
+            }
+        }
+        self.set_block(block.collapse(block.columns().start));
+    }
+
+    /// type the text in place of the block on every row of it, skipping the rows ending before it
+    fn insert_in_block(&mut self, block: Block, text: &str) {
+        if !self.check_writable() {
+            return;
+        }
+        self.begin_edit(block.columns().is_empty());
+        // bottom up, so that the chars of the rows above stay where they are
+        for (_, chars) in self.block_chars(block).into_iter().rev() {
+            let Some(chars) = chars else {
+                continue;
+            };
+            if !chars.is_empty() {
+                self.apply(Change::Delete {
+                    at: chars.start,
+                    text: self.editor_rows.text(chars.clone()),
+                });
+            }
+            self.apply(Change::Insert {
+                at: chars.start,
+                text: text.into(),
+            });
+        }
+        let column = block.columns().start;
+        let column = text.graphemes(true).fold(column, |column, grapheme| {
+            column + grapheme_width(grapheme, column)
+        });
+        self.set_block(block.collapse(column));
+        self.end_edit();
+    }
+
+    /// delete the block, or when it has no width the grapheme before or after it on every row
+    fn delete_in_block(&mut self, block: Block, forward: bool) {
+        let start = block.columns().start;
+        if !self.check_writable() || block.columns().is_empty() && !forward && start == 0 {
+            return;
+        }
+        self.begin_edit(false);
+        if !block.columns().is_empty() {
+            self.delete_block_text(block);
+            self.end_edit();
+            return;
+        }
+        // the block moves back over the narrowest of the graphemes deleted before it
+        let mut column = None;
+        let mut deleted = Vec::new();
+        for y in block.rows() {
+            let row = self.editor_rows.get_editor_row(y);
+            if row.column_chars(start..start).is_none() {
+                continue;
+            }
+            let step = row.column_step(start, forward);
+            let columns = if forward { start..step } else { step..start };
+            column = cmp::max(column, Some(columns.start));
+            if let Some(chars) = row.column_chars(columns) {
+                let row_start = self.editor_rows.row_to_char(y);
+                deleted.push(row_start + chars.start..row_start + chars.end);
+            }
+        }
+        for chars in deleted.into_iter().rev().filter(|it| !it.is_empty()) {
+            self.apply(Change::Delete {
+                at: chars.start,
+                text: self.editor_rows.text(chars),
+            });
+        }
+        self.set_block(block.collapse(column.unwrap_or(start)));
+        self.end_edit();
+    }
+
+    /// every cursor and its anchor as char indexes in the order of the buffer,
+    /// along with whether it is the primary one
+    fn cursor_chars(&self) -> Vec<(usize, Option<usize>, bool)> {
+        let primary = iter::once((&self.cursor_controller, true));
+        let mut positions: Vec<_> = primary
+            .chain(self.cursors.iter().map(|it| (it, false)))
+            .map(|(cursor, primary)| {
+                let at = self
+                    .editor_rows
+                    .char_index(cursor.cursor_x, cursor.cursor_y);
+                let anchor = cursor
+                    .anchor
+                    .map(|(x, y)| self.editor_rows.char_index(x, y));
+                (at, anchor, primary)
+            })
+            .collect();
+        positions.sort();
+        positions
+    }
+
+    /// put the cursors at the char indexes, merging the ones at the same place
+    fn set_cursor_chars(&mut self, mut positions: Vec<(usize, Option<usize>, bool)>) {
+        let len = self.editor_rows.len_chars();
+        positions.sort();
+        positions.dedup_by(|it, kept| {
+            let same = it.0 == kept.0;
+            if same {
+                kept.2 |= it.2;
+            }
+            same
+        });
+        self.cursors.clear();
+        for (at, anchor, primary) in positions {
+            let mut cursor = self.cursor_controller.clone();
+            (cursor.cursor_x, cursor.cursor_y) =
+                self.editor_rows.cursor_position(cmp::min(at, len));
+            cursor.anchor = anchor.map(|it| self.editor_rows.cursor_position(cmp::min(it, len)));
+            if primary {
+                self.cursor_controller = cursor;
+            } else {
+                self.cursors.push(cursor);
+            }
         }
-        self.editor_rows
-            .get_editor_row_mut(self.cursor_controller.cursor_y)
-            .insert_char(self.cursor_controller.cursor_x, ch);
+    }
+
+    /// merge the cursors moved to the same place
+    fn merge_cursors(&mut self) {
+        if !self.cursors.is_empty() {
+            self.set_cursor_chars(self.cursor_chars());
+        }
+    }
+
+    /// run the edit at every cursor, in the order of the buffer and as one undo step,
+    /// moving the cursors after each one along with the text it changed
+    fn edit_each_cursor(&mut self, typing: bool, mut edit: impl FnMut(&mut Self)) {
+        if self.cursors.is_empty() {
+            edit(self);
+            return;
+        }
+        if !self.check_writable() {
+            return;
+        }
+        let mut positions = self.cursor_chars();
+        self.begin_edit(typing);
+        for i in 0..positions.len() {
+            let (at, anchor, primary) = positions[i];
+            let len = self.editor_rows.len_chars();
+            self.cursor_controller.anchor = anchor.map(|it| self.editor_rows.cursor_position(it));
+            self.set_cursor(self.editor_rows.cursor_position(cmp::min(at, len)));
+            edit(self);
+            let delta = self.editor_rows.len_chars() as isize - len as isize;
+            let after = self.cursor_char();
+            positions[i] = (after, None, primary);
+            shift_positions(&mut positions[i + 1..], delta, after);
+        }
+        self.set_cursor_chars(positions);
+        self.end_edit();
+    }
+
+    /// add a cursor on the row above the topmost cursor, or below the bottommost one
+    pub fn add_cursor_vertically(&mut self, down: bool) {
+        let cursors = iter::once(&self.cursor_controller).chain(&self.cursors);
+        let edge = if down {
+            cursors.max_by_key(|it| it.cursor_y)
+        } else {
+            cursors.min_by_key(|it| it.cursor_y)
+        };
+        let mut moved = edge
+            .cloned()
+            .unwrap_or_else(|| self.cursor_controller.clone());
+        let edge_y = moved.cursor_y;
+        moved.anchor = None;
+        moved.move_cursor(
+            if down { KeyCode::Down } else { KeyCode::Up },
+            &self.editor_rows,
+        );
+        if moved.cursor_y == edge_y || moved.cursor_y >= self.editor_rows.number_of_rows() {
+            return;
+        }
+        // the new cursor becomes the primary one, so that the screen follows it
+        let mut cursor = self.cursor_controller.clone();
+        (cursor.cursor_x, cursor.cursor_y, cursor.anchor) = (moved.cursor_x, moved.cursor_y, None);
+        let primary = mem::replace(&mut self.cursor_controller, cursor);
+        self.cursors.push(primary);
+        self.merge_cursors();
+    }
+
+    /// select the word at the cursor, or add a cursor selecting the next occurrence
+    /// of the selection, wrapping around
+    pub fn add_cursor_at_next_match(&mut self) {
+        let Some(selection) = self.selection() else {
+            let (x, y) = self.cursor();
+            if y >= self.editor_rows.number_of_rows() {
+                return;
+            }
+            if let Some(word) = word_at(&self.editor_rows.row_content(y), x) {
+                self.cursor_controller.anchor = Some((word.start, y));
+                self.set_cursor((word.end, y));
+            }
+            return;
+        };
+        let selected = self.editor_rows.text(selection.clone());
+        let options = SearchOptions {
+            case: Case::Sensitive,
+            whole_word: selected.chars().all(|it| it.is_alphanumeric() || it == '_'),
+            regex: false,
+        };
+        let Ok(matcher) = Matcher::new(&selected, &options) else {
+            return;
+        };
+        let text = self.editor_rows.full_text();
+        let from = text
+            .char_indices()
+            .nth(selection.end)
+            .map_or(text.len(), |(i, _)| i);
+        let plain = PlainHighlight::new();
+        let syntax_highlight = self.syntax_highlight.as_deref().unwrap_or(&plain);
+        let found = matcher
+            .captures_at(&text, from, syntax_highlight)
+            .or_else(|| matcher.captures_at(&text, 0, syntax_highlight))
+            .and_then(|it| it.get(0));
+        let Some(found) = found else {
+            return;
+        };
+        let start = text[..found.start()].chars().count();
+        let end = start + found.as_str().chars().count();
+        let selected_already = iter::once(&self.cursor_controller)
+            .chain(&self.cursors)
+            .any(|it| self.selection_of(it) == Some(start..end));
+        if selected_already {
+            self.status_message
+                .set_message("No more occurrences".into());
+            return;
+        }
+        let mut cursor = self.cursor_controller.clone();
+        cursor.anchor = Some(self.editor_rows.cursor_position(start));
+        (cursor.cursor_x, cursor.cursor_y) = self.editor_rows.cursor_position(end);
+        let primary = mem::replace(&mut self.cursor_controller, cursor);
+        self.cursors.push(primary);
+        self.merge_cursors();
+    }
+
+    /// put a cursor at the end of every row of the selection
+    pub fn split_selection(&mut self) {
+        let Some(selection) = self.selection() else {
+            self.status_message.set_message("Nothing selected".into());
+            return;
+        };
+        let (_, first) = self.editor_rows.cursor_position(selection.start);
+        let (end_x, last) = self.editor_rows.cursor_position(selection.end);
+        let mut positions: Vec<_> = (first..last)
+            .map(|y| {
+                let row_chars = self.editor_rows.row_content(y).chars().count();
+                (self.editor_rows.row_to_char(y) + row_chars, None, false)
+            })
+            .collect();
+        if end_x > 0 || positions.is_empty() {
+            positions.push((selection.end, None, true));
+        } else if let Some(last) = positions.last_mut() {
+            last.2 = true;
+        }
+        self.set_cursor_chars(positions);
+    }
+
+    /// delete the selected chars in the edit open, whether there were some
+    fn delete_selection(&mut self) -> bool {
+        let Some(selection) = self.selection() else {
+            return false;
+        };
+        self.apply(Change::Delete {
+            at: selection.start,
+            text: self.editor_rows.text(selection.clone()),
+        });
+        self.set_cursor(self.editor_rows.cursor_position(selection.start));
+        true
+    }
+
+    /// delete the selected chars as an edit of their own, whether there were some
+    fn delete_selected(&mut self) -> bool {
+        if self.selection().is_none() {
+            return false;
+        }
+        self.begin_edit(false);
+        self.delete_selection();
+        self.end_edit();
+        true
+    }
+
+    /// cursor position
+    pub fn cursor(&self) -> (usize, usize) {
+        (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        )
+    }
+
+    /// move the cursor to the position
+    pub fn set_cursor(&mut self, (x, y): (usize, usize)) {
+        self.cursor_controller.cursor_x = x;
+        self.cursor_controller.cursor_y = y;
+    }
+
+    /// whether the buffer has unsaved changes
+    pub fn is_dirty(&self) -> bool {
+        self.editor_rows.history.is_modified()
+    }
+
+    /// whether the buffer can be edited, tell why not otherwise
+    pub fn check_writable(&mut self) -> bool {
+        if self.editor_rows.read_only {
+            self.status_message
+                .set_message("File is read-only. Use the readonly command to edit it.".into());
+        }
+        !self.editor_rows.read_only
+    }
+
+    /// open an undo step for an edit at the cursor
+    pub fn begin_edit(&mut self, typing: bool) {
+        if self.edit_depth == 0 {
+            self.editor_rows.history.begin_step(self.cursor(), typing);
+        }
+        self.edit_depth += 1;
+    }
+
+    /// close the undo step of the edit
+    pub fn end_edit(&mut self) {
+        self.edit_depth = self.edit_depth.saturating_sub(1);
+        if self.edit_depth == 0 {
+            self.editor_rows.history.end_step(self.cursor());
+        }
+    }
+
+    /// apply a change to the rows and highlight the rows touched, the selection is dropped
+    pub fn apply(&mut self, change: Change) {
+        self.cursor_controller.anchor = None;
+        let rows = self.editor_rows.apply(change);
+        self.update_syntax(rows);
+    }
+
+    /// update syntax of the rows
+    pub fn update_syntax(&mut self, rows: Range<usize>) {
         if let Some(it) = self.syntax_highlight.as_ref() {
-            it.update_syntax(
-                self.cursor_controller.cursor_y,
-                &mut self.editor_rows.row_contents,
+            rows.for_each(|at| it.update_syntax(at, &mut self.editor_rows));
+        }
+    }
+
+    /// undo the last step
+    pub fn undo(&mut self) {
+        self.single_cursor();
+        if !self.check_writable() {
+            return;
+        }
+        match self.editor_rows.undo() {
+            Some((cursor, rows)) => {
+                self.cursor_controller.anchor = None;
+                self.set_cursor(cursor);
+                self.update_syntax(rows);
+            }
+            None => self
+                .status_message
+                .set_message("Already at oldest change".into()),
+        }
+    }
+
+    /// redo the last undone step
+    pub fn redo(&mut self) {
+        self.single_cursor();
+        if !self.check_writable() {
+            return;
+        }
+        match self.editor_rows.redo() {
+            Some((cursor, rows)) => {
+                self.cursor_controller.anchor = None;
+                self.set_cursor(cursor);
+                self.update_syntax(rows);
+            }
+            None => self
+                .status_message
+                .set_message("Already at newest change".into()),
+        }
+    }
+
+    /// replace the whole buffer with the text as one undoable step, unless it is the same
+    pub fn replace_contents(&mut self, contents: String) {
+        let len = self.editor_rows.len_chars();
+        if self.editor_rows.text(0..len) == contents {
+            return;
+        }
+        self.begin_edit(false);
+        if len > 0 {
+            self.apply(Change::Delete {
+                at: 0,
+                text: self.editor_rows.text(0..len),
+            });
+        }
+        if !contents.is_empty() {
+            self.apply(Change::Insert {
+                at: 0,
+                text: contents,
+            });
+        }
+        self.set_cursor((0, 0));
+        self.end_edit();
+    }
+
+    /// read the file again as one undoable step, keeping the cursor where it was
+    pub fn reload(&mut self) {
+        self.reopen(None)
+    }
+
+    /// read the file again in the encoding, or the one detected
+    pub fn reopen(&mut self, encoding: Option<Encoding>) {
+        let Some(filename) = self.editor_rows.filename.clone() else {
+            self.status_message
+                .set_message("No file name to reload".into());
+            return;
+        };
+        let cursor = self.cursor();
+        // a large file is read again as needed, its history starting over,
+        // rather than read whole and kept twice in the undo step
+        let reloaded = if self.editor_rows.large {
+            EditorRows::from_large_file(filename.clone(), encoding).map(|editor_rows| {
+                self.editor_rows = editor_rows;
+                self.single_cursor();
+                self.cursor_controller.anchor = None;
+                self.load_rows();
+            })
+        } else {
+            file::load(&filename, encoding).map(|loaded| {
+                self.replace_contents(loaded.text());
+                self.editor_rows.encoding = loaded.encoding;
+                self.editor_rows.line_ending = loaded.line_ending;
+                self.editor_rows.final_newline = loaded.final_newline;
+                self.editor_rows.disk_state = loaded.disk_state;
+                self.editor_rows.history.mark_saved();
+            })
+        };
+        match reloaded {
+            Ok(()) => {
+                self.checked_disk_state = None;
+                let y = cmp::min(cursor.1, self.editor_rows.number_of_rows());
+                let x = if y < self.editor_rows.number_of_rows() {
+                    cmp::min(cursor.0, self.editor_rows.row_len(y))
+                } else {
+                    0
+                };
+                self.set_cursor((x, y));
+                self.status_message
+                    .set_message(format!("\"{}\" reloaded", filename.display()));
+            }
+            Err(err) => self
+                .status_message
+                .set_message(format!("Can't reload! I/O error: {}", err)),
+        }
+    }
+
+    /// reload the file changed on the disk when the buffer is clean, warn once otherwise,
+    /// whether it did either
+    pub fn check_disk(&mut self) -> bool {
+        if !self.editor_rows.changed_on_disk() {
+            return false;
+        }
+        let disk_state = self
+            .editor_rows
+            .filename
+            .as_deref()
+            .map(DiskState::of)
+            .unwrap_or_default();
+        if self.checked_disk_state == Some(disk_state) {
+            return false;
+        }
+        self.checked_disk_state = Some(disk_state);
+        if !self.is_dirty() && disk_state.exists() {
+            self.reload();
+        } else {
+            self.status_message.set_message(
+                "WARNING!! File changed on disk. Use the reload command or Ctrl-S to overwrite."
+                    .into(),
             );
         }
-        self.cursor_controller.cursor_x += 1;
-        self.dirty += 1;
+        true
+    }
+
+    /// keep the swap file up to date with unsaved changes, whether it failed
+    pub fn update_swap_file(&mut self) -> bool {
+        let result = self.swap_file.update(&self.editor_rows);
+        if let Err(err) = &result {
+            self.status_message
+                .set_message(format!("Can't write swap file! I/O error: {}", err));
+        }
+        result.is_err()
+    }
+
+    /// the work done while no key is pressed, whether the screen needs to be drawn again,
+    /// the outline shown in the status bar is scanned here rather than on every key
+    pub fn idle(&mut self) -> bool {
+        let checked = self.check_disk();
+        let failed = self.update_swap_file();
+        let scanned = self.update_outline();
+        checked || failed || scanned
+    }
+
+    /// read the rest of a large file before searching it if wanted,
+    /// the rows read so far are searched otherwise, false if cancelled
+    fn load_to_search(&mut self) -> crossterm::Result<bool> {
+        if !self.editor_rows.is_loading() {
+            return Ok(true);
+        }
+        match self.choose(
+            "Read the rest of the large file to search all of it? (y/n)",
+            &['y', 'n'],
+        )? {
+            Some('y') => match self.editor_rows.load_all() {
+                Ok(()) => Ok(true),
+                Err(err) => {
+                    self.status_message
+                        .set_message(format!("Can't read the file! I/O error: {}", err));
+                    Ok(false)
+                }
+            },
+            Some(_) => Ok(true),
+            None => Ok(false),
+        }
+    }
+
+    /// search incrementally, leaving the cursor on the match found
+    pub fn find(&mut self) -> crossterm::Result<()> {
+        self.single_cursor();
+        if !self.load_to_search()? {
+            return Ok(());
+        }
+        let cursor = self.cursor();
+        self.search = Some(Search::new(
+            self.cursor(),
+            (
+                self.cursor_controller.row_offset,
+                self.cursor_controller.column_offset,
+            ),
+        ));
+        let found = prompt!(
+            *self,
+            "Search: {} (Esc / Arrows / Enter, Alt-C / Alt-W / Alt-R)",
+            callback = Writer::find_callback
+        );
+        self.search = None;
+        if found.is_some() && self.cursor() != cursor {
+            let at = self.editor_rows.char_index(cursor.0, cursor.1);
+            self.editor_rows.marks.push_jump(at);
+        }
+        Ok(())
+    }
+
+    /// follow the search as the pattern is typed, the options toggled and the arrows pressed
+    fn find_callback(&mut self, pattern: &str, key: KeyEvent) -> String {
+        let Some(search) = self.search.as_mut() else {
+            return String::new();
+        };
+        let options = self.search_options;
+        if key.modifiers == KeyModifiers::ALT {
+            match key.code {
+                KeyCode::Char('c') => self.search_options.toggle_case(),
+                KeyCode::Char('w') => self.search_options.whole_word ^= true,
+                KeyCode::Char('r') => self.search_options.regex ^= true,
+                _ => {}
+            }
+        }
+        let found = match key.code {
+            KeyCode::Esc => {
+                let (row_offset, column_offset) = search.saved_offsets;
+                self.cursor_controller.row_offset = row_offset;
+                self.cursor_controller.column_offset = column_offset;
+                let cursor = search.saved_cursor;
+                self.set_cursor(cursor);
+                return String::new();
+            }
+            KeyCode::Up | KeyCode::Left => search.step(false).cloned(),
+            KeyCode::Down | KeyCode::Right => search.step(true).cloned(),
+            _ if search.pattern != pattern || options != self.search_options => {
+                let (x, y) = search.saved_cursor;
+                let plain = PlainHighlight::new();
+                let syntax_highlight = self.syntax_highlight.as_deref().unwrap_or(&plain);
+                search.find(
+                    &self.editor_rows,
+                    pattern,
+                    &self.search_options,
+                    syntax_highlight,
+                );
+                search
+                    .select_from(self.editor_rows.char_index(x, y))
+                    .cloned()
+            }
+            _ => None,
+        };
+        if let Some(found) = found {
+            self.set_cursor(self.editor_rows.cursor_position(found.start));
+        }
+        format!(
+            "{} {}",
+            self.search
+                .as_ref()
+                .map(Search::counter)
+                .unwrap_or_default(),
+            self.search_options.describe()
+        )
+    }
+
+    /// replace the matches of a pattern, asking at each one, as one undoable step
+    pub fn replace(&mut self) -> crossterm::Result<()> {
+        self.single_cursor();
+        if !self.check_writable() || !self.load_to_search()? {
+            return Ok(());
+        }
+        let Some(pattern) = prompt!(*self, "Replace: {} (Esc to cancel)") else {
+            return Ok(());
+        };
+        let matcher = match Matcher::new(&pattern, &self.search_options) {
+            Ok(matcher) => matcher,
+            Err(err) => {
+                self.status_message
+                    .set_message(format!("Invalid pattern: {}", err));
+                return Ok(());
+            }
+        };
+        let Some(replacement) = prompt!(
+            *self,
+            "Replace with: {} (Esc to cancel, $1 for groups)",
+            allow_empty = true
+        ) else {
+            return Ok(());
+        };
+        let plain = PlainHighlight::new();
+        // the '\n' ending the last row stays, or the row would be lost with it
+        let mut text = self
+            .editor_rows
+            .text(0..self.editor_rows.len_chars().saturating_sub(1));
+        // byte and char index the next match is looked for from
+        let (mut bytes, mut chars) = (0, 0);
+        let (mut replaced, mut replace_all) = (0, false);
+        self.begin_edit(false);
+        loop {
+            let syntax_highlight = self.syntax_highlight.as_deref().unwrap_or(&plain);
+            let Some(captures) = matcher.captures_at(&text, bytes, syntax_highlight) else {
+                break;
+            };
+            let found = captures.get(0).unwrap();
+            let mut with = String::new();
+            if self.search_options.regex {
+                captures.expand(&replacement, &mut with);
+            } else {
+                with.push_str(&replacement);
+            }
+            let range = found.range();
+            let start = chars + text[bytes..range.start].chars().count();
+            let len = found.as_str().chars().count();
+            self.set_cursor(self.editor_rows.cursor_position(start));
+            let answer = if replace_all {
+                Some('a')
+            } else {
+                let mut search = Search::new(self.cursor(), (0, 0));
+                search.matches.push(start..start + len);
+                self.search = Some(search);
+                let answer = self.choose(
+                    "Replace this match? (y)es, (n)o, (a)ll or (q)uit",
+                    &['y', 'n', 'a', 'q'],
+                )?;
+                self.search = None;
+                answer
+            };
+            match answer {
+                Some('y' | 'a') => {
+                    replace_all = answer == Some('a');
+                    self.apply(Change::Delete {
+                        at: start,
+                        text: self.editor_rows.text(start..start + len),
+                    });
+                    if !with.is_empty() {
+                        self.apply(Change::Insert {
+                            at: start,
+                            text: with.clone(),
+                        });
+                    }
+                    text.replace_range(range.clone(), &with);
+                    bytes = range.start + with.len();
+                    chars = start + with.chars().count();
+                    replaced += 1;
+                }
+                Some('n') => {
+                    bytes = range.end;
+                    chars = start + len;
+                }
+                _ => break,
+            }
+        }
+        if replaced > 0 {
+            let cursor = self.editor_rows.cursor_position(chars);
+            self.set_cursor(cursor);
+        }
+        self.end_edit();
+        self.status_message
+            .set_message(format!("Replaced {} occurrences", replaced));
+        Ok(())
+    }
+
+    /// ask a question answered with one of the keys, None if aborted with Esc
+    pub fn choose(&mut self, question: &str, keys: &[char]) -> crossterm::Result<Option<char>> {
+        loop {
+            self.status_message.set_message(question.into());
+            self.refresh_screen()?;
+            match Reader.wait_key()? {
+                KeyEvent {
+                    code: KeyCode::Esc,
+                    modifiers: KeyModifiers::NONE,
+                } => {
+                    self.status_message.set_message(String::new());
+                    return Ok(None);
+                }
+                KeyEvent {
+                    code: KeyCode::Char(ch),
+                    modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                } if keys.contains(&ch) => {
+                    self.status_message.set_message(String::new());
+                    return Ok(Some(ch));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// convert the line endings used on save
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        if !self.check_writable() {
+            return;
+        }
+        if self.editor_rows.line_ending != line_ending {
+            self.begin_edit(false);
+            self.apply(Change::LineEnding {
+                from: self.editor_rows.line_ending,
+                to: line_ending,
+            });
+            self.end_edit();
+        }
+        self.status_message
+            .set_message(format!("Line endings set to {}", line_ending.name()));
+    }
+
+    /// change the encoding used on save
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        if !self.check_writable() {
+            return;
+        }
+        if self.editor_rows.encoding != encoding {
+            self.begin_edit(false);
+            self.apply(Change::Encoding {
+                from: self.editor_rows.encoding,
+                to: encoding,
+            });
+            self.end_edit();
+        }
+        self.status_message
+            .set_message(format!("Encoding set to {}", encoding.name()));
+    }
+
+    /// insert char
+    pub fn insert_char(&mut self, ch: char) {
+        if let Some(block) = self.block {
+            self.insert_in_block(block, &ch.to_string());
+            return;
+        }
+        let typing = iter::once(&self.cursor_controller)
+            .chain(&self.cursors)
+            .all(|it| it.anchor.is_none());
+        self.edit_each_cursor(typing, |it| it.insert_char_at_cursor(ch));
+    }
+
+    /// insert the char at the cursor, in place of the selection
+    fn insert_char_at_cursor(&mut self, ch: char) {
+        if !self.check_writable() {
+            return;
+        }
+        self.begin_edit(self.selection().is_none());
+        self.delete_selection();
+        let (x, y) = self.cursor();
+        if y == self.editor_rows.number_of_rows() {
+            self.editor_rows.insert_row(y, String::new());
+        }
+        let mut at = self.editor_rows.char_index(x, y);
+        let before: String = self
+            .editor_rows
+            .row_content(y)
+            .chars()
+            .take(at - self.editor_rows.row_to_char(y))
+            .collect();
+        // a closing bracket typed first on its row takes a level off the indentation
+        if matches!(ch, '}' | ')' | ']') && before.chars().all(|c| c == ' ' || c == '\t') {
+            let dedent = self.indent().map_or(0, |it| it.last_level(&before));
+            if dedent > 0 {
+                at -= dedent;
+                self.apply(Change::Delete {
+                    at,
+                    text: self.editor_rows.text(at..at + dedent),
+                });
+            }
+        }
+        self.apply(Change::Insert {
+            at,
+            text: ch.to_string(),
+        });
+        self.set_cursor(self.editor_rows.cursor_position(at + 1));
+        self.end_edit();
+    }
+
+    /// one level of indentation for the file type, or as found in the first rows,
+    /// None when neither tells
+    fn indent(&self) -> Option<Indent> {
+        self.syntax_highlight
+            .as_ref()
+            .and_then(|it| it.indent())
+            .or_else(|| {
+                let rows = cmp::min(self.editor_rows.number_of_rows(), INDENT_SCAN_ROWS);
+                Indent::detect((0..rows).map(|y| self.editor_rows.row_content(y)))
+            })
     }
 
     /// insert new line
     pub fn insert_newline(&mut self) {
-        if self.cursor_controller.cursor_x == 0 {
-            self.editor_rows
-                .insert_row(self.cursor_controller.cursor_y, String::new())
-        } else {
-            let current_row = self
-                .editor_rows
-                .get_editor_row_mut(self.cursor_controller.cursor_y);
-            let new_row_content = current_row.row_content[self.cursor_controller.cursor_x..].into();
-            current_row
-                .row_content
-                .truncate(self.cursor_controller.cursor_x);
-            EditorRows::render_row(current_row);
-            self.editor_rows
-                .insert_row(self.cursor_controller.cursor_y + 1, new_row_content);
-            if let Some(it) = self.syntax_highlight.as_ref() {
-                it.update_syntax(
-                    self.cursor_controller.cursor_y,
-                    &mut self.editor_rows.row_contents,
-                );
-                it.update_syntax(
-                    self.cursor_controller.cursor_y + 1,
-                    &mut self.editor_rows.row_contents,
-                );
+        self.block = None;
+        self.edit_each_cursor(false, Writer::insert_newline_at_cursor);
+    }
+
+    /// split the row at the cursor, in place of the selection
+    fn insert_newline_at_cursor(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+        self.begin_edit(false);
+        self.delete_selection();
+        let (x, y) = self.cursor();
+        let at = self.editor_rows.char_index(x, y);
+        let mut text = String::from("\n");
+        let mut cursor = at + 1;
+        if y < self.editor_rows.number_of_rows() {
+            let row_content = self.editor_rows.row_content(y).into_owned();
+            let (before, rest) = row_content.split_at(
+                row_content
+                    .char_indices()
+                    .nth(at - self.editor_rows.row_to_char(y))
+                    .map_or(row_content.len(), |(i, _)| i),
+            );
+            // the blanks after the cursor would only push the rest of the row off the indentation
+            let blanks = rest.chars().take_while(|c| *c == ' ' || *c == '\t').count();
+            if blanks > 0 {
+                self.apply(Change::Delete {
+                    at,
+                    text: self.editor_rows.text(at..at + blanks),
+                });
+            }
+            let indentation: String = before
+                .chars()
+                .take_while(|c| *c == ' ' || *c == '\t')
+                .collect();
+            text.push_str(&indentation);
+            // one more level after an opening bracket of the code,
+            // with the closing one right after the cursor going below
+            let row = self.highlighted_row(y);
+            let before_chars = before.chars().count();
+            let opener = row
+                .grapheme_highlights()
+                .into_iter()
+                .rfind(|(chars, grapheme, _)| *chars < before_chars && !grapheme.trim().is_empty())
+                .filter(|(_, _, highlight)| highlight.is_code())
+                .and_then(|(_, grapheme, _)| match grapheme {
+                    "{" => Some('}'),
+                    "(" => Some(')'),
+                    "[" => Some(']'),
+                    _ => None,
+                });
+            // with no level known only the indentation is kept
+            let indent = self.indent();
+            if let (Some(_), Some(indent)) = (opener, indent) {
+                text.push_str(&indent.unit());
+            }
+            cursor = at + text.chars().count();
+            if indent.is_some()
+                && opener.is_some_and(|closer| rest.trim_start().starts_with(closer))
+            {
+                text.push('\n');
+                text.push_str(&indentation);
             }
         }
-        self.cursor_controller.cursor_x = 0;
-        self.cursor_controller.cursor_y += 1;
-        self.dirty += 1;
+        self.apply(Change::Insert { at, text });
+        self.set_cursor(self.editor_rows.cursor_position(cursor));
+        self.end_edit();
     }
 
-    /// delete char
+    /// delete char, or the selection
     pub fn delete_char(&mut self) {
-        if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
+        if let Some(block) = self.block {
+            self.delete_in_block(block, false);
             return;
         }
-        if self.cursor_controller.cursor_y == 0 && self.cursor_controller.cursor_x == 0 {
+        self.edit_each_cursor(false, Writer::delete_char_at_cursor);
+    }
+
+    /// delete the char after the cursor, or the selection
+    pub fn delete_char_forward(&mut self) {
+        if let Some(block) = self.block {
+            self.delete_in_block(block, true);
             return;
         }
-        if self.cursor_controller.cursor_x > 0 {
-            self.editor_rows
-                .get_editor_row_mut(self.cursor_controller.cursor_y)
-                .delete_char(self.cursor_controller.cursor_x - 1);
-            self.cursor_controller.cursor_x -= 1;
+        if !self.check_writable() {
+            return;
+        }
+        self.edit_each_cursor(false, |it| {
+            if it.selection().is_none() {
+                it.cursor_controller
+                    .move_cursor(KeyCode::Right, &it.editor_rows);
+            }
+            it.delete_char_at_cursor();
+        });
+    }
+
+    /// delete the char before the cursor, or the selection
+    fn delete_char_at_cursor(&mut self) {
+        if !self.check_writable() || self.delete_selected() {
+            return;
+        }
+        let (x, y) = self.cursor();
+        if y == self.editor_rows.number_of_rows() {
+            return;
+        }
+        if y == 0 && x == 0 {
+            return;
+        }
+        self.begin_edit(false);
+        let end = self.editor_rows.char_index(x, y);
+        let start = if x > 0 {
+            self.editor_rows.char_index(x - 1, y)
         } else {
-            let previous_row_content = self
-                .editor_rows
-                .get_render(self.cursor_controller.cursor_y - 1);
-            self.cursor_controller.cursor_x = previous_row_content.len();
-            self.editor_rows
-                .join_adjacent_rows(self.cursor_controller.cursor_y);
-            self.cursor_controller.cursor_y -= 1;
+            end - 1
+        };
+        self.apply(Change::Delete {
+            at: start,
+            text: self.editor_rows.text(start..end),
+        });
+        self.set_cursor(self.editor_rows.cursor_position(start));
+        self.end_edit();
+    }
+
+    /// delete from the cursor to the start of the previous or the next word, or the selection
+    pub fn delete_word(&mut self, forward: bool) {
+        if let Some(block) = self.block {
+            self.delete_in_block(block, forward);
+            return;
         }
-        if let Some(it) = self.syntax_highlight.as_ref() {
-            it.update_syntax(
-                self.cursor_controller.cursor_y,
-                &mut self.editor_rows.row_contents,
-            );
+        self.edit_each_cursor(false, |it| it.delete_word_at_cursor(forward));
+    }
+
+    /// delete a word at the cursor, or its selection
+    fn delete_word_at_cursor(&mut self, forward: bool) {
+        if !self.check_writable() || self.delete_selected() {
+            return;
+        }
+        let cursor = self.cursor();
+        let at = self.cursor_char();
+        self.cursor_controller.move_cursor_far(
+            if forward {
+                KeyCode::Right
+            } else {
+                KeyCode::Left
+            },
+            &self.editor_rows,
+        );
+        // past the last row only its end is reached, its '\n' stays
+        let to = cmp::min(self.cursor_char(), self.editor_rows.last_row_end());
+        self.set_cursor(cursor);
+        if at == to || forward && to < at {
+            return;
+        }
+        let (start, end) = (cmp::min(at, to), cmp::max(at, to));
+        self.begin_edit(false);
+        self.apply(Change::Delete {
+            at: start,
+            text: self.editor_rows.text(start..end),
+        });
+        self.set_cursor(self.editor_rows.cursor_position(start));
+        self.end_edit();
+    }
+
+    /// copy the selection, or the row of the cursor when nothing is selected, and delete it on cut
+    pub fn copy(&mut self, cut: bool) {
+        if cut && !self.check_writable() {
+            return;
+        }
+        let (_, y) = self.cursor();
+        let (range, clipboard) = match (self.block, self.selection()) {
+            (Some(block), _) => (0..0, Clipboard::new(self.block_text(block), Shape::Block)),
+            (None, Some(selection)) => {
+                let text = self.editor_rows.text(selection.clone());
+                (selection, Clipboard::new(text, Shape::Chars))
+            }
+            (None, None) if y < self.editor_rows.number_of_rows() => {
+                let start = self.editor_rows.row_to_char(y);
+                let text = self.editor_rows.row_content(y).into_owned();
+                let end = start + text.chars().count() + 1;
+                (start..end, Clipboard::new(text, Shape::Rows))
+            }
+            _ => return,
+        };
+        let sent = clipboard.send_to_terminal().unwrap_or(false);
+        self.status_message.set_message(format!(
+            "{} {} {}{}",
+            if cut { "Cut" } else { "Copied" },
+            match clipboard.shape {
+                Shape::Chars => clipboard.text.chars().count(),
+                Shape::Rows | Shape::Block => clipboard.rows(),
+            },
+            match clipboard.shape {
+                Shape::Chars => "chars",
+                Shape::Rows => "rows",
+                Shape::Block => "block rows",
+            },
+            if sent {
+                ""
+            } else {
+                ", too long for the terminal clipboard"
+            }
+        ));
+        self.clipboard = Some(clipboard);
+        if let Some(block) = self.block.filter(|_| cut) {
+            self.begin_edit(false);
+            self.delete_block_text(block);
+            self.end_edit();
+        } else if cut {
+            self.begin_edit(false);
+            self.apply(Change::Delete {
+                at: range.start,
+                text: self.editor_rows.text(range.clone()),
+            });
+            self.set_cursor(self.editor_rows.cursor_position(range.start));
+            self.end_edit();
+        }
+    }
+
+    /// paste the clipboard in place of the selection or at the cursor,
+    /// rows copied whole go above the row of the cursor
+    pub fn paste(&mut self) {
+        let Some(block) = self.block else {
+            self.edit_each_cursor(false, Writer::paste_at_cursor);
+            return;
+        };
+        // chars are typed on every row of the block, anything else goes at its top left corner
+        let typed = self
+            .clipboard
+            .as_ref()
+            .filter(|it| it.shape == Shape::Chars && !it.text.contains('\n'))
+            .map(|it| it.text.clone());
+        if let Some(text) = typed {
+            self.insert_in_block(block, &text);
+            return;
+        }
+        if !self.check_writable() {
+            return;
+        }
+        self.begin_edit(false);
+        self.delete_block_text(block);
+        let y = *block.rows().start();
+        let x = self
+            .editor_rows
+            .get_editor_row(y)
+            .get_row_content_x(block.columns().start);
+        self.set_cursor((x, y));
+        self.block = None;
+        self.paste_at_cursor();
+        self.end_edit();
+    }
+
+    /// paste the clipboard at the cursor
+    fn paste_at_cursor(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+        let Some(clipboard) = self.clipboard.as_ref() else {
+            self.status_message.set_message("Clipboard is empty".into());
+            return;
+        };
+        let (mut text, shape) = (clipboard.text.clone(), clipboard.shape);
+        self.begin_edit(false);
+        let replaced = self.delete_selection();
+        let (x, y) = self.cursor();
+        match shape {
+            Shape::Rows if !replaced => {
+                let rows = text.matches('\n').count() + 1;
+                self.editor_rows.insert_row(y, text);
+                self.update_syntax(y..y + rows);
+                self.set_cursor((x, y + rows));
+            }
+            Shape::Block => self.paste_block(&text),
+            _ => {
+                if shape == Shape::Rows {
+                    text.push('\n');
+                }
+                self.paste_chars(&text);
+            }
+        }
+        self.end_edit();
+    }
+
+    /// insert the rows of the text one below the other at the render column of the cursor,
+    /// padding the rows ending before it with spaces and adding rows past the end of the file
+    fn paste_block(&mut self, text: &str) {
+        let (x, y) = self.cursor();
+        let column = if y < self.editor_rows.number_of_rows() {
+            self.cursor_controller
+                .get_render_x(&self.editor_rows.row_content(y))
+        } else {
+            0
+        };
+        for (i, piece) in text.split('\n').enumerate() {
+            let y = y + i;
+            if piece.is_empty() {
+                continue;
+            }
+            while y >= self.editor_rows.number_of_rows() {
+                self.apply(Change::Insert {
+                    at: self.editor_rows.len_chars(),
+                    text: "\n".into(),
+                });
+            }
+            let row = self.editor_rows.get_editor_row(y);
+            let start = self.editor_rows.row_to_char(y);
+            let (at, padding) = match row.column_chars(column..column) {
+                Some(chars) => (start + chars.start, 0),
+                None => (
+                    start + row.row_content.chars().count(),
+                    column - row.render.width(),
+                ),
+            };
+            self.apply(Change::Insert {
+                at,
+                text: " ".repeat(padding) + piece,
+            });
+        }
+        self.set_cursor((x, y));
+    }
+
+    /// insert the text at the cursor, the rows in the middle of it as whole rows
+    fn paste_chars(&mut self, text: &str) {
+        let (x, y) = self.cursor();
+        if y == self.editor_rows.number_of_rows() {
+            self.editor_rows.insert_row(y, String::new());
+        }
+        let at = self.editor_rows.char_index(x, y);
+        let mut lines = text.split('\n');
+        let first = lines.next().unwrap_or_default();
+        let rest: Vec<&str> = lines.collect();
+        let Some((last, middle)) = rest.split_last() else {
+            self.apply(Change::Insert {
+                at,
+                text: first.into(),
+            });
+            self.set_cursor(self.editor_rows.cursor_position(at + first.chars().count()));
+            return;
+        };
+        // split the row at the cursor, the rows in between go before its second half
+        self.apply(Change::Insert {
+            at,
+            text: format!("{}\n", first),
+        });
+        if !middle.is_empty() {
+            self.editor_rows.insert_row(y + 1, middle.join("\n"));
+            self.update_syntax(y + 1..y + 1 + middle.len());
+        }
+        let start = self.editor_rows.row_to_char(y + 1 + middle.len());
+        self.apply(Change::Insert {
+            at: start,
+            text: (*last).into(),
+        });
+        self.set_cursor(
+            self.editor_rows
+                .cursor_position(start + last.chars().count()),
+        );
+    }
+
+    /// read the rows of a large file down to the screen below the cursor
+    pub fn load_rows(&mut self) {
+        let rows = cmp::max(
+            self.cursor_controller.cursor_y,
+            self.cursor_controller.row_offset,
+        ) + self.win_size.1;
+        if let Err(err) = self.editor_rows.load_rows(rows) {
+            self.status_message
+                .set_message(format!("Can't read the file! I/O error: {}", err));
         }
-        self.dirty += 1;
     }
 
     /// refresh screen
     pub fn refresh_screen(&mut self) -> crossterm::Result<()> {
-        execute!(stdout(), SetBackgroundColor(Color::from(BACKGROUND_COLOR)));
+        execute!(stdout(), SetBackgroundColor(Color::from(BACKGROUND_COLOR)))?;
+        self.load_rows();
+        self.matching_bracket = self.find_matching_bracket();
         self.cursor_controller.scroll(&self.editor_rows);
         queue!(self.editor_contents, cursor::Hide, cursor::MoveTo(0, 0))?;
         self.draw_rows();
@@ -271,28 +2074,51 @@ impl Writer {
     }
 }
 
+/// move the cursor positions after an edit by the chars it added or removed,
+/// keeping them from going back before where the edit left its cursor
+fn shift_positions(positions: &mut [(usize, Option<usize>, bool)], delta: isize, after: usize) {
+    for (at, anchor, _) in positions {
+        *at = cmp::max(at.saturating_add_signed(delta), after);
+        *anchor = anchor.map(|it| cmp::max(it.saturating_add_signed(delta), after));
+    }
+}
+
 #[macro_export]
 macro_rules! prompt {
     ($writer:expr, $args:tt) => {
-        prompt!($writer, $args, callback = |&_, _, _| {})
+        prompt!($writer, $args, callback = |&_, _, _| String::new())
+    };
+    ($writer:expr, $args:tt, callback = $callback:expr) => {
+        prompt!($writer, $args, callback = $callback, allow_empty = false)
     };
-    ($writer:expr, $args:tt, callback = $callback:expr) => {{
+    ($writer:expr, $args:tt, allow_empty = $allow_empty:expr) => {
+        prompt!(
+            $writer,
+            $args,
+            callback = |&_, _, _| String::new(),
+            allow_empty = $allow_empty
+        )
+    };
+    ($writer:expr, $args:tt, callback = $callback:expr, allow_empty = $allow_empty:expr) => {{
         let writer: &mut Writer = &mut $writer;
         let mut input = String::with_capacity(32);
+        let mut info = String::new();
+        let mut confirmed = false;
         loop {
-            writer.status_message.set_message(format!($args, input));
+            writer
+                .status_message
+                .set_message(format!($args, input) + &info);
             writer.refresh_screen()?;
-            match Reader.read_key()? {
+            match Reader.wait_key()? {
                 // confirm
                 KeyEvent {
                     code: KeyCode::Enter,
                     modifiers: KeyModifiers::NONE,
-                } => {
-                    if !input.is_empty() {
-                        writer.status_message.set_message(String::new());
-                        $callback(writer, &input, KeyCode::Enter);
-                        break;
-                    }
+                } if $allow_empty || !input.is_empty() => {
+                    writer.status_message.set_message(String::new());
+                    $callback(writer, &input, KeyEvent::from(KeyCode::Enter));
+                    confirmed = true;
+                    break;
                 }
                 // abort
                 KeyEvent {
@@ -301,7 +2127,7 @@ macro_rules! prompt {
                 } => {
                     writer.status_message.set_message(String::new());
                     input.clear();
-                    $callback(writer, &input, KeyCode::Esc);
+                    $callback(writer, &input, KeyEvent::from(KeyCode::Esc));
                     break;
                 }
                 // delete
@@ -320,14 +2146,48 @@ macro_rules! prompt {
                     KeyCode::Char(ch) => ch,
                     _ => unreachable!(),
                 }),
-                _ => {}
+                // other keys are left to the callback, modifiers included
+                key => {
+                    info = $callback(writer, &input, key);
+                    continue;
+                }
             }
-            $callback(writer, &input, KeyCode::Null);
+            info = $callback(writer, &input, KeyEvent::from(KeyCode::Null));
         }
-        if input.is_empty() {
-            None
-        } else {
+        if confirmed {
             Some(input)
+        } else {
+            None
         }
     }};
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shift_positions_follows_an_insert() {
+        let mut positions = [(10, None, false), (20, Some(14), true)];
+        shift_positions(&mut positions, 3, 6);
+        assert_eq!(positions, [(13, None, false), (23, Some(17), true)]);
+    }
+
+    #[test]
+    fn shift_positions_follows_a_delete() {
+        let mut positions = [(10, Some(8), false), (20, None, false)];
+        shift_positions(&mut positions, -2, 4);
+        assert_eq!(positions, [(8, Some(6), false), (18, None, false)]);
+    }
+
+    #[test]
+    fn shift_positions_stops_at_the_edit() {
+        // a delete reaching over the next cursors leaves them where it ended
+        let mut positions = [(6, Some(5), false), (30, None, true)];
+        shift_positions(&mut positions, -5, 3);
+        assert_eq!(positions, [(3, Some(3), false), (25, None, true)]);
+        let mut positions = [(1, None, false)];
+        shift_positions(&mut positions, -4, 0);
+        assert_eq!(positions, [(0, None, false)]);
+    }
+}
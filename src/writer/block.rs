@@ -0,0 +1,38 @@
+use std::cmp;
+use std::ops::{Range, RangeInclusive};
+
+/// Block struct - a rectangle of render columns over a run of rows,
+/// from the anchor corner to the corner moved along with the cursor
+#[derive(Copy, Clone)]
+pub struct Block {
+    pub anchor: (usize, usize),
+    pub corner: (usize, usize),
+}
+
+impl Block {
+    /// constructor - no rows selected but the one at the corner, no columns wide
+    pub fn new(column: usize, y: usize) -> Self {
+        Self {
+            anchor: (column, y),
+            corner: (column, y),
+        }
+    }
+
+    /// the rows, top to bottom
+    pub fn rows(&self) -> RangeInclusive<usize> {
+        cmp::min(self.anchor.1, self.corner.1)..=cmp::max(self.anchor.1, self.corner.1)
+    }
+
+    /// the render columns, left to right
+    pub fn columns(&self) -> Range<usize> {
+        cmp::min(self.anchor.0, self.corner.0)..cmp::max(self.anchor.0, self.corner.0)
+    }
+
+    /// the block over the same rows with no width, at the column
+    pub fn collapse(&self, column: usize) -> Self {
+        Self {
+            anchor: (column, self.anchor.1),
+            corner: (column, self.corner.1),
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use ropey::{Rope, RopeBuilder};
+use std::borrow::Cow;
+use std::io;
+use std::ops::Range;
+
+/// Text Buffer struct
+///
+/// Rows are kept in a rope, each one terminated by `'\n'`, so that an empty
+/// buffer has no rows at all and looking up or editing a row stays cheap no
+/// matter how large the file is. Columns are counted in chars.
+#[derive(Default)]
+pub struct TextBuffer {
+    rope: Rope,
+}
+
+impl TextBuffer {
+    /// constructor
+    pub fn new() -> Self {
+        Self { rope: Rope::new() }
+    }
+
+    /// build the buffer from rows
+    pub fn from_rows<'a>(rows: impl Iterator<Item = &'a str>) -> Self {
+        let mut builder = RopeBuilder::new();
+        rows.for_each(|row| {
+            builder.append(row);
+            builder.append("\n");
+        });
+        Self {
+            rope: builder.finish(),
+        }
+    }
+
+    /// rows number
+    pub fn number_of_rows(&self) -> usize {
+        self.rope.len_lines() - 1
+    }
+
+    /// get the at row without its terminator
+    pub fn row(&self, at: usize) -> Cow<'_, str> {
+        let line = self.rope.line(at);
+        line.slice(..line.len_chars() - 1).into()
+    }
+
+    /// chars number of the at row
+    pub fn row_len(&self, at: usize) -> usize {
+        self.rope.line(at).len_chars() - 1
+    }
+
+    /// iterate over all rows
+    pub fn rows(&self) -> impl Iterator<Item = Cow<'_, str>> {
+        (0..self.number_of_rows()).map(|at| self.row(at))
+    }
+
+    /// chars number of the whole buffer
+    pub fn len_chars(&self) -> usize {
+        self.rope.len_chars()
+    }
+
+    /// char index of the start of the at row
+    pub fn row_to_char(&self, at: usize) -> usize {
+        self.rope.line_to_char(at)
+    }
+
+    /// row containing the char index
+    pub fn char_to_row(&self, at: usize) -> usize {
+        self.rope.char_to_line(at)
+    }
+
+    /// the chars in the range
+    pub fn slice(&self, range: Range<usize>) -> String {
+        self.rope.slice(range).to_string()
+    }
+
+    /// insert text at the char index
+    pub fn insert(&mut self, at: usize, text: &str) {
+        self.rope.insert(at, text);
+    }
+
+    /// remove the chars in the range, return them
+    pub fn remove(&mut self, range: Range<usize>) -> String {
+        let text = self.rope.slice(range.clone()).to_string();
+        self.rope.remove(range);
+        text
+    }
+
+    /// write the rows with their terminators as they are kept
+    pub fn write_raw<W: io::Write + ?Sized>(&self, out: &mut W) -> io::Result<usize> {
+        for chunk in self.rope.chunks() {
+            out.write_all(chunk.as_bytes())?;
+        }
+        Ok(self.rope.len_bytes())
+    }
+
+    /// write rows joined by the separator
+    pub fn write_to<W: io::Write + ?Sized>(
+        &self,
+        out: &mut W,
+        separator: &str,
+    ) -> io::Result<usize> {
+        let mut len = 0;
+        for (i, row) in self.rows().enumerate() {
+            if i > 0 {
+                out.write_all(separator.as_bytes())?;
+                len += separator.len();
+            }
+            out.write_all(row.as_bytes())?;
+            len += row.len();
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_kept_without_their_terminators() {
+        let buffer = TextBuffer::from_rows(["one", "", "three"].into_iter());
+        assert_eq!(buffer.number_of_rows(), 3);
+        assert_eq!(buffer.row(0), "one");
+        assert_eq!(buffer.row_len(1), 0);
+        assert_eq!(buffer.row_to_char(2), 5);
+        assert_eq!(buffer.char_to_row(3), 0);
+        assert_eq!(buffer.char_to_row(4), 1);
+        assert_eq!(buffer.char_to_row(5), 2);
+        assert_eq!(buffer.char_to_row(buffer.len_chars()), 3);
+    }
+
+    #[test]
+    fn an_empty_buffer_has_no_rows() {
+        let buffer = TextBuffer::new();
+        assert_eq!(buffer.number_of_rows(), 0);
+        assert_eq!(buffer.rows().count(), 0);
+    }
+
+    #[test]
+    fn a_row_left_without_terminator_is_not_counted() {
+        // the reason rows are kept terminated by the edits, see `EditorRows::apply`
+        let mut buffer = TextBuffer::from_rows(["ab"].into_iter());
+        assert_eq!(buffer.remove(2..3), "\n");
+        assert_eq!((buffer.number_of_rows(), buffer.len_chars()), (0, 2));
+    }
+
+    #[test]
+    fn write_joins_the_rows() {
+        let mut buffer = TextBuffer::from_rows(["a", "b"].into_iter());
+        buffer.insert(1, "x\ny");
+        let mut joined = Vec::new();
+        assert_eq!(buffer.write_to(&mut joined, "\r\n").unwrap(), 8);
+        assert_eq!(joined, b"ax\r\ny\r\nb");
+        let mut raw = Vec::new();
+        buffer.write_raw(&mut raw).unwrap();
+        assert_eq!(raw, b"ax\ny\nb\n");
+    }
+}
@@ -0,0 +1,98 @@
+use crate::OSC52_LIMIT;
+use std::env;
+use std::io::{self, stdout, Write};
+
+/// Shape enum - how the text was taken from the buffer, and so how it is pasted back
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Shape {
+    Chars,
+    Rows,
+    /// the rows of a block selection, pasted one below the other at the same column
+    Block,
+}
+
+/// Clipboard struct - the text cut or copied last
+pub struct Clipboard {
+    pub text: String,
+    pub shape: Shape,
+}
+
+impl Clipboard {
+    /// constructor - the text of rows and blocks is without a last newline
+    pub fn new(text: String, shape: Shape) -> Self {
+        Self { text, shape }
+    }
+
+    /// number of rows of the text
+    pub fn rows(&self) -> usize {
+        self.text.matches('\n').count() + 1
+    }
+
+    /// copy the text to the terminal clipboard with OSC 52, through tmux when inside it,
+    /// whether it was small enough to be sent
+    pub fn send_to_terminal(&self) -> io::Result<bool> {
+        let mut text = self.text.clone();
+        if self.shape == Shape::Rows {
+            text.push('\n');
+        }
+        let encoded = base64(text.as_bytes());
+        if encoded.len() > OSC52_LIMIT {
+            return Ok(false);
+        }
+        let sequence = format!("\x1b]52;c;{}\x07", encoded);
+        let mut out = stdout();
+        out.write_all(sequence.as_bytes())?;
+        if env::var_os("TMUX").is_some() {
+            let passthrough = format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"));
+            out.write_all(passthrough.as_bytes())?;
+        }
+        out.flush()?;
+        Ok(true)
+    }
+}
+
+/// standard base64 with padding
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+            group | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_pads_the_last_group() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn base64_uses_the_whole_alphabet() {
+        assert_eq!(base64(&[0xfb, 0xff, 0xbf]), "+/+/");
+        assert_eq!(base64(&[0, 0, 0]), "AAAA");
+        assert_eq!(base64("héllo\n".as_bytes()), "aMOpbGxvCg==");
+    }
+
+    #[test]
+    fn rows_counts_the_last_row_without_newline() {
+        assert_eq!(Clipboard::new("one".into(), Shape::Chars).rows(), 1);
+        assert_eq!(Clipboard::new("one\ntwo".into(), Shape::Rows).rows(), 2);
+    }
+}
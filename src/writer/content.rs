@@ -1,10 +1,22 @@
+use crate::writer::buffer::TextBuffer;
+use crate::writer::encoding::{EncodedWriter, Encoding};
+use crate::writer::file::{self, DiskState, Loader};
 use crate::writer::highlight::{HighlightType, SyntaxHighlight};
+use crate::writer::history::{Change, History};
+use crate::writer::marks::Marks;
 use crate::{Writer, TAB_SIZE};
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::io::{stdout, Error, ErrorKind, Write};
-use std::path::PathBuf;
-use std::{env, fs, io};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::{cmp, fs, io, iter};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 /// Editor Contents struct
+#[derive(Default)]
 pub struct EditorContents {
     pub content: String,
 }
@@ -49,7 +61,7 @@ impl Write for EditorContents {
     }
 }
 
-/// Row struct
+/// Row struct - a rendered view of one row of the text buffer
 #[derive(Default)]
 pub struct Row {
     pub row_content: String,
@@ -60,157 +72,918 @@ pub struct Row {
 
 impl Row {
     /// constructor
-    pub fn new(row_content: String, render: String) -> Self {
-        Self {
+    pub fn new(row_content: String) -> Self {
+        let mut row = Self {
             row_content,
-            render,
-            highlight: Vec::new(),
-            is_comment: false,
+            ..Default::default()
+        };
+        EditorRows::render_row(&mut row);
+        row
+    }
+
+    /// convert a render column into a grapheme index of the row content
+    pub fn get_row_content_x(&self, render_x: usize) -> usize {
+        let mut current_render_x = 0;
+        for (cursor_x, grapheme) in self.row_content.graphemes(true).enumerate() {
+            current_render_x += grapheme_width(grapheme, current_render_x);
+            if current_render_x > render_x {
+                return cursor_x;
+            }
         }
+        self.row_content.graphemes(true).count()
     }
 
-    /// insert char
-    pub fn insert_char(&mut self, at: usize, ch: char) {
-        self.row_content.insert(at, ch);
-        EditorRows::render_row(self);
+    /// the graphemes of the row content with their char index and render byte index
+    fn render_graphemes(&self) -> impl Iterator<Item = (usize, usize, &str)> {
+        let (mut chars, mut render_x, mut index) = (0, 0, 0);
+        self.row_content.graphemes(true).map(move |grapheme| {
+            let item = (chars, index, grapheme);
+            chars += grapheme.chars().count();
+            let width = grapheme_width(grapheme, render_x);
+            render_x += width;
+            index += match control_picture(grapheme) {
+                _ if grapheme == "\t" => width,
+                Some(picture) => picture.len(),
+                None => grapheme.len(),
+            };
+            item
+        })
     }
 
-    /// delete char
-    pub fn delete_char(&mut self, at: usize) {
-        self.row_content.remove(at);
-        EditorRows::render_row(self)
+    /// render byte index of the first grapheme starting at or after the char index
+    pub fn render_index(&self, char_x: usize) -> usize {
+        self.render_graphemes()
+            .find(|(chars, _, _)| *chars >= char_x)
+            .map_or(self.render.len(), |(_, index, _)| index)
     }
 
-    pub fn get_row_content_x(&self, render_x: usize) -> usize {
-        let mut current_render_x = 0;
-        for (cursor_x, ch) in self.row_content.chars().enumerate() {
-            if ch == '\t' {
-                current_render_x += (TAB_SIZE - 1) - (current_render_x % TAB_SIZE);
+    /// the graphemes of the row content with their char index and highlight
+    pub fn grapheme_highlights(&self) -> Vec<(usize, &str, HighlightType)> {
+        self.render_graphemes()
+            .map(|(chars, index, grapheme)| {
+                let highlight = self
+                    .highlight
+                    .get(index)
+                    .copied()
+                    .unwrap_or(HighlightType::Normal);
+                (chars, grapheme, highlight)
+            })
+            .collect()
+    }
+
+    /// draw the chars of the row content with the highlight over the syntax colors
+    pub fn highlight_chars(&mut self, chars: Range<usize>, highlight_type: HighlightType) {
+        if self.highlight.len() != self.render.len() {
+            self.highlight = vec![HighlightType::Normal; self.render.len()];
+        }
+        let start = self.render_index(chars.start);
+        let end = self.render_index(chars.end);
+        self.highlight[start..end].fill(highlight_type);
+    }
+
+    /// the graphemes of the row content with their char index and render column
+    pub fn grapheme_columns(&self) -> impl Iterator<Item = (usize, usize, &str)> {
+        let (mut chars, mut render_x) = (0, 0);
+        self.row_content.graphemes(true).map(move |grapheme| {
+            let item = (chars, render_x, grapheme);
+            chars += grapheme.chars().count();
+            render_x += grapheme_width(grapheme, render_x);
+            item
+        })
+    }
+
+    /// the chars of the graphemes drawn over the render columns, a tab partly in them included,
+    /// or with no columns where chars would be inserted, None when the row ends before them
+    pub fn column_chars(&self, columns: Range<usize>) -> Option<Range<usize>> {
+        if self.render.width() < columns.start {
+            return None;
+        }
+        let chars_where = |found: &dyn Fn(usize, usize) -> bool| {
+            self.grapheme_columns()
+                .find(|(_, column, grapheme)| {
+                    found(*column, column + grapheme_width(grapheme, *column))
+                })
+                .map_or(self.row_content.chars().count(), |(chars, _, _)| chars)
+        };
+        let start = chars_where(&|_, end| end > columns.start);
+        if columns.is_empty() {
+            return Some(start..start);
+        }
+        Some(start..chars_where(&|start, _| start >= columns.end))
+    }
+
+    /// the render column of the next or the previous grapheme, one column at a time past the end
+    pub fn column_step(&self, column: usize, forward: bool) -> usize {
+        let width = self.render.width();
+        if forward {
+            self.grapheme_columns()
+                .map(|(_, it, _)| it)
+                .find(|it| *it > column)
+                .unwrap_or(cmp::max(column + 1, width))
+        } else if column > width {
+            column - 1
+        } else {
+            self.grapheme_columns()
+                .map(|(_, it, _)| it)
+                .take_while(|it| *it < column)
+                .last()
+                .unwrap_or(0)
+        }
+    }
+
+    /// draw a blank past the end of the row with the highlight, for a cursor there
+    pub fn highlight_end(&mut self, highlight_type: HighlightType) {
+        if self.highlight.len() != self.render.len() {
+            self.highlight = vec![HighlightType::Normal; self.render.len()];
+        }
+        self.render.push(' ');
+        self.highlight.push(highlight_type);
+    }
+
+    /// the render bytes visible from the column offset within the width,
+    /// along with the padding left by a wide grapheme cut at the left edge
+    pub fn render_range(&self, column_offset: usize, width: usize) -> (usize, Range<usize>) {
+        let mut column = 0;
+        let mut padding = 0;
+        let mut start = None;
+        for (i, grapheme) in self.render.grapheme_indices(true) {
+            let grapheme_width = grapheme.width();
+            if column < column_offset {
+                column += grapheme_width;
+                padding = column.saturating_sub(column_offset);
+                continue;
             }
-            current_render_x += 1;
-            if current_render_x > render_x {
-                return cursor_x;
+            let start = *start.get_or_insert(i);
+            if column + grapheme_width > column_offset + width {
+                return (padding, start..i);
             }
+            column += grapheme_width;
+        }
+        let start = start.unwrap_or(self.render.len());
+        (padding, start..self.render.len())
+    }
+}
+
+/// display width of a grapheme drawn at the given render column
+pub fn grapheme_width(grapheme: &str, render_x: usize) -> usize {
+    if grapheme == "\t" {
+        TAB_SIZE - render_x % TAB_SIZE
+    } else if let Some(picture) = control_picture(grapheme) {
+        picture.len()
+    } else {
+        grapheme.width()
+    }
+}
+
+/// caret notation of a control char, such as `^@` for NUL
+fn control_picture(grapheme: &str) -> Option<String> {
+    let mut chars = grapheme.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) if ch.is_control() && ch != '\t' => Some(match ch {
+            '\0'..='\x1f' | '\x7f' => format!("^{}", (ch as u8 ^ 0x40) as char),
+            _ => "^?".into(),
+        }),
+        _ => None,
+    }
+}
+
+/// the longest prefix of the string that fits in the width
+pub fn truncate_width(string: &str, width: usize) -> &str {
+    let mut current_width = 0;
+    for (i, grapheme) in string.grapheme_indices(true) {
+        current_width += grapheme.width();
+        if current_width > width {
+            return &string[..i];
+        }
+    }
+    string
+}
+
+/// char index of the at grapheme
+fn char_index(row_content: &str, at: usize) -> usize {
+    row_content
+        .graphemes(true)
+        .take(at)
+        .map(|grapheme| grapheme.chars().count())
+        .sum()
+}
+
+/// grapheme indexes where the words of the row start, punctuation counted as words
+pub fn word_starts(row_content: &str) -> Vec<usize> {
+    let mut graphemes = 0;
+    let mut starts = Vec::new();
+    for word in row_content.split_word_bounds() {
+        if !word.trim().is_empty() {
+            starts.push(graphemes);
+        }
+        graphemes += word.graphemes(true).count();
+    }
+    starts
+}
+
+/// grapheme range of the word under the grapheme index, or just before it,
+/// a word being a run of graphemes starting with a letter, a digit or `_`
+pub fn word_at(row_content: &str, at: usize) -> Option<Range<usize>> {
+    let is_word: Vec<bool> = row_content
+        .graphemes(true)
+        .map(|grapheme| {
+            grapheme
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_alphanumeric() || c == '_')
+        })
+        .collect();
+    let at = if is_word.get(at) == Some(&true) {
+        at
+    } else if at > 0 && is_word.get(at - 1) == Some(&true) {
+        at - 1
+    } else {
+        return None;
+    };
+    let start = is_word[..at]
+        .iter()
+        .rposition(|it| !it)
+        .map_or(0, |i| i + 1);
+    let end = is_word[at..]
+        .iter()
+        .position(|it| !it)
+        .map_or(is_word.len(), |i| at + i);
+    Some(start..end)
+}
+
+/// grapheme index of the first char that is not blank
+pub fn first_non_blank(row_content: &str) -> usize {
+    row_content
+        .graphemes(true)
+        .take_while(|grapheme| grapheme.trim().is_empty())
+        .count()
+}
+
+/// Line Ending enum
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    /// detect the style used by most lines of the contents
+    pub fn detect(contents: &str) -> Self {
+        let lines = contents.matches('\n').count();
+        let crlf_lines = contents.matches("\r\n").count();
+        if crlf_lines * 2 > lines {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    /// the line separator
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    /// name shown in the status bar
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
         }
-        0
     }
 }
 
 /// Editor Rows struct
 pub struct EditorRows {
-    pub row_contents: Vec<Row>,
+    buffer: TextBuffer,
+    is_comment: Vec<bool>,
     pub filename: Option<PathBuf>,
+    pub encoding: Encoding,
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+    pub disk_state: DiskState,
+    pub history: History,
+    pub marks: Marks,
+    pub read_only: bool,
+    pub large: bool,
+    loader: Option<Loader>,
+    full_text: RefCell<Option<Rc<str>>>,
+}
+
+impl Default for EditorRows {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl EditorRows {
     /// constructor
-    pub fn new(syntax_highlight: &mut Option<Box<dyn SyntaxHighlight>>) -> Self {
-        match env::args().nth(1) {
-            None => Self {
-                row_contents: Vec::new(),
-                filename: None,
-            },
-            Some(file) => Self::from_file(file.into(), syntax_highlight),
+    pub fn new() -> Self {
+        Self::empty(None)
+    }
+
+    /// an empty buffer, created on the disk on first save
+    pub fn empty(filename: Option<PathBuf>) -> Self {
+        Self {
+            buffer: TextBuffer::new(),
+            is_comment: Vec::new(),
+            filename,
+            encoding: Encoding::Utf8,
+            line_ending: LineEnding::Lf,
+            final_newline: true,
+            disk_state: DiskState::default(),
+            history: History::new(),
+            marks: Marks::new(),
+            read_only: false,
+            large: false,
+            loader: None,
+            full_text: RefCell::new(None),
+        }
+    }
+
+    /// a buffer of the rows, not backed by a file
+    pub fn from_rows<'a>(rows: impl Iterator<Item = &'a str>) -> Self {
+        let buffer = TextBuffer::from_rows(rows);
+        Self {
+            is_comment: vec![false; buffer.number_of_rows()],
+            buffer,
+            ..Self::empty(None)
         }
     }
 
-    /// display from file
+    /// display from file, a file that does not exist yet opens empty,
+    /// one of the large size or more is read as needed
     pub fn from_file(
         file: PathBuf,
         syntax_highlight: &mut Option<Box<dyn SyntaxHighlight>>,
-    ) -> Self {
-        let file_contents = fs::read_to_string(&file).expect("Unable to read file");
-        let mut row_contents = Vec::new();
+        large_file_size: u64,
+    ) -> io::Result<Self> {
+        let large = fs::metadata(&file).is_ok_and(|metadata| metadata.len() >= large_file_size);
+        if large {
+            return Self::from_large_file(file, None);
+        }
         file.extension()
             .and_then(|ext| ext.to_str())
             .map(|ext| Writer::select_syntax(ext).map(|syntax| syntax_highlight.insert(syntax)));
-        file_contents.lines().enumerate().for_each(|(i, line)| {
-            let mut row = Row::new(line.into(), String::new());
-            Self::render_row(&mut row);
-            row_contents.push(row);
-            if let Some(it) = syntax_highlight {
-                it.update_syntax(i, &mut row_contents)
+        let loaded = match file::load(&file, None) {
+            Ok(loaded) => loaded,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return Ok(Self {
+                    read_only: !file::is_writable(&file),
+                    ..Self::empty(Some(file))
+                })
             }
-        });
-        Self {
-            row_contents,
+            Err(err) => {
+                return Err(Error::new(
+                    err.kind(),
+                    format!("Can't open {}: {}", file.display(), err),
+                ))
+            }
+        };
+        let buffer = TextBuffer::from_rows(loaded.rows());
+        let mut editor_rows = Self {
+            is_comment: vec![false; buffer.number_of_rows()],
+            buffer,
+            read_only: !file::is_writable(&file),
             filename: Some(file),
+            encoding: loaded.encoding,
+            line_ending: loaded.line_ending,
+            final_newline: loaded.final_newline,
+            disk_state: loaded.disk_state,
+            history: History::new(),
+            marks: Marks::new(),
+            large: false,
+            loader: None,
+            full_text: RefCell::new(None),
+        };
+        if let Some(it) = syntax_highlight {
+            editor_rows.update_all_syntax(it.as_ref());
         }
+        Ok(editor_rows)
     }
 
-    /// save to the disk
-    pub fn save(&self) -> io::Result<usize> {
-        match &self.filename {
-            None => Err(Error::new(ErrorKind::Other, "no file name specified")),
-            Some(name) => {
-                let mut file = fs::OpenOptions::new().write(true).create(true).open(name)?;
-                let contents: String = self
-                    .row_contents
-                    .iter()
-                    .map(|it| it.row_content.as_str())
-                    .collect::<Vec<&str>>()
-                    .join("\n");
-                file.set_len(contents.len() as u64)?;
-                file.write_all(contents.as_bytes())?;
-                Ok(contents.as_bytes().len())
+    /// display from a large file without highlighting, reading only the rows looked at,
+    /// in the encoding detected unless one is given
+    pub fn from_large_file(file: PathBuf, encoding: Option<Encoding>) -> io::Result<Self> {
+        let disk_state = DiskState::of(&file);
+        let mut loader = Loader::open(&file).map_err(|err| {
+            Error::new(
+                err.kind(),
+                format!("Can't open {}: {}", file.display(), err),
+            )
+        })?;
+        if let Some(encoding) = encoding {
+            loader.encoding = encoding;
+        }
+        let mut editor_rows = Self {
+            encoding: loader.encoding,
+            read_only: !file::is_writable(&file),
+            disk_state,
+            large: true,
+            loader: Some(loader),
+            ..Self::empty(Some(file))
+        };
+        editor_rows.load_rows(1)?;
+        Ok(editor_rows)
+    }
+
+    /// whether rows of the file are still to be read
+    pub fn is_loading(&self) -> bool {
+        self.loader.is_some()
+    }
+
+    /// read the file until it has more than the rows number,
+    /// on error the rest is dropped and the buffer turns read-only
+    pub fn load_rows(&mut self, rows: usize) -> io::Result<()> {
+        while self.number_of_rows() <= rows {
+            let Some(loader) = self.loader.as_mut() else {
+                break;
+            };
+            match loader.next_rows() {
+                Ok(Some(text)) => {
+                    if self.buffer.number_of_rows() == 0 {
+                        self.line_ending = loader.line_ending.unwrap_or(LineEnding::Lf);
+                    }
+                    let len = self.buffer.len_chars();
+                    self.is_comment
+                        .extend(iter::repeat_n(false, text.matches('\n').count()));
+                    self.buffer.insert(len, &text);
+                    self.full_text.get_mut().take();
+                }
+                Ok(None) => {
+                    self.final_newline = loader.final_newline;
+                    if let Some(line_ending) = loader.line_ending {
+                        self.line_ending = line_ending;
+                    }
+                    self.loader = None;
+                }
+                Err(err) => {
+                    self.loader = None;
+                    self.read_only = true;
+                    return Err(err);
+                }
             }
         }
+        Ok(())
+    }
+
+    /// read the rest of the file
+    pub fn load_all(&mut self) -> io::Result<()> {
+        self.load_rows(usize::MAX - 1)
+    }
+
+    /// forget the rest of the file, the buffer is about to be replaced
+    pub fn stop_loading(&mut self) {
+        self.loader = None;
+    }
+
+    /// whether the file on the disk is not the one read or written last
+    pub fn changed_on_disk(&self) -> bool {
+        self.filename
+            .as_deref()
+            .is_some_and(|filename| DiskState::of(filename) != self.disk_state)
+    }
+
+    /// save to the disk
+    pub fn save(&mut self, backup: bool) -> io::Result<usize> {
+        match self.filename.clone() {
+            None => Err(Error::other("no file name specified")),
+            Some(_) if self.read_only => Err(Error::other("file is read-only")),
+            Some(name) => self.write_file(&name, backup),
+        }
+    }
+
+    /// save the file under a new name, which the buffer takes only once it is written
+    pub fn save_as(&mut self, filename: PathBuf, backup: bool) -> io::Result<usize> {
+        let len = self.write_file(&filename, backup)?;
+        self.filename = Some(filename);
+        Ok(len)
+    }
+
+    /// write the whole file in its encoding and line ending
+    fn write_file(&mut self, path: &Path, backup: bool) -> io::Result<usize> {
+        self.load_all()?;
+        let len = file::write_atomically(path, backup, |out| {
+            let mut out = EncodedWriter::new(out, self.encoding)?;
+            let line_ending = self.line_ending.as_str();
+            self.buffer.write_to(&mut out, line_ending)?;
+            if self.final_newline && self.number_of_rows() > 0 {
+                out.write_all(line_ending.as_bytes())?;
+            }
+            Ok(out.written())
+        })?;
+        self.disk_state = DiskState::of(path);
+        Ok(len)
+    }
+
+    /// write every row terminated by '\n' regardless of the file format
+    pub fn write_contents(&self, out: &mut dyn Write) -> io::Result<usize> {
+        self.buffer.write_raw(out)
+    }
+
+    /// chars number of the whole buffer
+    pub fn len_chars(&self) -> usize {
+        self.buffer.len_chars()
     }
 
     /// rows number
     pub fn number_of_rows(&self) -> usize {
-        self.row_contents.len()
+        self.buffer.number_of_rows()
+    }
+
+    /// get the at row content
+    pub fn row_content(&self, at: usize) -> Cow<'_, str> {
+        self.buffer.row(at)
     }
 
-    /// get the at render
-    pub fn get_render(&self, at: usize) -> &String {
-        &self.row_contents[at].render
+    /// graphemes number of the at row
+    pub fn row_len(&self, at: usize) -> usize {
+        self.buffer.row(at).graphemes(true).count()
     }
 
-    /// get the at editor row
-    pub fn get_editor_row(&self, at: usize) -> &Row {
-        &self.row_contents[at]
+    /// get the at editor row, rendered but not highlighted
+    pub fn get_editor_row(&self, at: usize) -> Row {
+        let mut row = Row::new(self.buffer.row(at).into_owned());
+        row.is_comment = self.is_comment[at];
+        row
     }
 
-    /// get the at editor row as mut
-    pub fn get_editor_row_mut(&mut self, at: usize) -> &mut Row {
-        &mut self.row_contents[at]
+    /// whether the at row ends inside a multiline comment
+    pub fn is_comment(&self, at: usize) -> bool {
+        self.is_comment[at]
+    }
+
+    /// set whether the at row ends inside a multiline comment
+    pub fn set_comment(&mut self, at: usize, is_comment: bool) {
+        self.is_comment[at] = is_comment;
+    }
+
+    /// highlight every row from the top
+    pub fn update_all_syntax(&mut self, syntax_highlight: &dyn SyntaxHighlight) {
+        let mut in_comment = false;
+        for at in 0..self.number_of_rows() {
+            let mut row = self.get_editor_row(at);
+            syntax_highlight.highlight_row(&mut row, in_comment);
+            in_comment = row.is_comment;
+            self.is_comment[at] = in_comment;
+        }
+    }
+
+    /// char index of the cursor position
+    pub fn char_index(&self, x: usize, y: usize) -> usize {
+        let start = self.buffer.row_to_char(y);
+        if y == self.number_of_rows() {
+            start
+        } else {
+            start + char_index(&self.buffer.row(y), x)
+        }
+    }
+
+    /// char index of the end of the last row, before its '\n'
+    pub fn last_row_end(&self) -> usize {
+        self.len_chars().saturating_sub(1)
+    }
+
+    /// cursor position of the char index - the graphemes of its row starting before it
+    pub fn cursor_position(&self, at: usize) -> (usize, usize) {
+        let y = self.buffer.char_to_row(at);
+        if y == self.number_of_rows() {
+            return (0, y);
+        }
+        let mut column = at - self.buffer.row_to_char(y);
+        let x = self
+            .buffer
+            .row(y)
+            .graphemes(true)
+            .take_while(|grapheme| {
+                let starts_before = column > 0;
+                column = column.saturating_sub(grapheme.chars().count());
+                starts_before
+            })
+            .count();
+        (x, y)
+    }
+
+    /// char index of the start of the at row
+    pub fn row_to_char(&self, at: usize) -> usize {
+        self.buffer.row_to_char(at)
+    }
+
+    /// the text between two char indexes
+    pub fn text(&self, range: Range<usize>) -> String {
+        self.buffer.slice(range)
+    }
+
+    /// the text of the whole buffer, kept until the next change
+    pub fn full_text(&self) -> Rc<str> {
+        self.full_text
+            .borrow_mut()
+            .get_or_insert_with(|| self.text(0..self.len_chars()).into())
+            .clone()
+    }
+
+    /// apply a change and record it in the history, return the rows touched
+    pub fn apply(&mut self, change: Change) -> Range<usize> {
+        let Some(change) = self.keep_rows_terminated(change) else {
+            return 0..0;
+        };
+        let rows = self.apply_change(&change);
+        let cursor = match change {
+            Change::Insert { at, .. } | Change::Delete { at, .. } => self.cursor_position(at),
+            Change::LineEnding { .. } | Change::Encoding { .. } => (0, 0),
+        };
+        self.history.record(change, cursor);
+        rows
+    }
+
+    /// the change made to leave every row terminated by '\n': a delete stops before
+    /// the last one unless it takes the rest of the buffer, an insert at the end brings one,
+    /// None when nothing is left to change
+    fn keep_rows_terminated(&self, change: Change) -> Option<Change> {
+        let len = self.len_chars();
+        match change {
+            Change::Delete { at, mut text }
+                if at > 0
+                    && at + text.chars().count() == len
+                    && text.ends_with('\n')
+                    && self.buffer.slice(at - 1..at) != "\n" =>
+            {
+                text.pop();
+                (!text.is_empty()).then_some(Change::Delete { at, text })
+            }
+            Change::Insert { at, mut text }
+                if at == len && !text.is_empty() && !text.ends_with('\n') =>
+            {
+                text.push('\n');
+                Some(Change::Insert { at, text })
+            }
+            change => Some(change),
+        }
+    }
+
+    /// apply a change without recording it, return the rows touched
+    fn apply_change(&mut self, change: &Change) -> Range<usize> {
+        self.marks.shift(change);
+        self.full_text.get_mut().take();
+        match change {
+            Change::Insert { at, text } => {
+                let row = self.buffer.char_to_row(*at);
+                let rows = text.matches('\n').count();
+                let index = cmp::min(row + 1, self.is_comment.len());
+                self.buffer.insert(*at, text);
+                self.is_comment
+                    .splice(index..index, iter::repeat_n(false, rows));
+                row..cmp::min(row + rows + 1, self.number_of_rows())
+            }
+            Change::Delete { at, text } => {
+                let row = self.buffer.char_to_row(*at);
+                let rows = text.matches('\n').count();
+                let index = cmp::min(row + 1, self.is_comment.len() - rows);
+                self.buffer.remove(*at..*at + text.chars().count());
+                self.is_comment.drain(index..index + rows);
+                row..cmp::min(row + 1, self.number_of_rows())
+            }
+            Change::LineEnding { to, .. } => {
+                self.line_ending = *to;
+                0..0
+            }
+            Change::Encoding { to, .. } => {
+                self.encoding = *to;
+                0..0
+            }
+        }
+    }
+
+    /// revert the last step, return the cursor to restore and the rows touched
+    pub fn undo(&mut self) -> Option<((usize, usize), Range<usize>)> {
+        let (changes, cursor) = self.history.undo()?;
+        Some((cursor, self.apply_changes(&changes)))
+    }
+
+    /// apply the last reverted step again, return the cursor to restore and the rows touched
+    pub fn redo(&mut self) -> Option<((usize, usize), Range<usize>)> {
+        let (changes, cursor) = self.history.redo()?;
+        Some((cursor, self.apply_changes(&changes)))
+    }
+
+    /// apply changes without recording them, return the rows touched by any of them
+    fn apply_changes(&mut self, changes: &[Change]) -> Range<usize> {
+        changes
+            .iter()
+            .map(|change| self.apply_change(change))
+            .filter(|rows| !rows.is_empty())
+            .reduce(|a, b| cmp::min(a.start, b.start)..cmp::max(a.end, b.end))
+            .map(|rows| rows.start..cmp::min(rows.end, self.number_of_rows()))
+            .unwrap_or(0..0)
     }
 
     /// insert row
     pub fn insert_row(&mut self, at: usize, contents: String) {
-        let mut new_row = Row::new(contents, String::new());
-        EditorRows::render_row(&mut new_row);
-        self.row_contents.insert(at, new_row);
+        self.apply(Change::Insert {
+            at: self.buffer.row_to_char(at),
+            text: contents + "\n",
+        });
     }
 
     /// backspacing at the start of the line
     pub fn join_adjacent_rows(&mut self, at: usize) {
-        let current_row = self.row_contents.remove(at);
-        let previous_row = self.get_editor_row_mut(at - 1);
-        previous_row.row_content.push_str(&current_row.row_content);
-        Self::render_row(previous_row);
+        self.apply(Change::Delete {
+            at: self.buffer.row_to_char(at) - 1,
+            text: "\n".into(),
+        });
     }
 
     /// render row
     pub fn render_row(row: &mut Row) {
-        let mut index = 0;
-        let capacity = row
-            .row_content
-            .chars()
-            .fold(0, |acc, next| acc + if next == '\t' { TAB_SIZE } else { 1 });
-        row.render = String::with_capacity(capacity);
-        row.row_content.chars().for_each(|c| {
-            index += 1;
-            if c == '\t' {
-                row.render.push(' ');
-                while index % TAB_SIZE != 0 {
-                    row.render.push(' ');
-                    index += 1
-                }
+        let mut render_x = 0;
+        row.render = String::with_capacity(row.row_content.len());
+        row.row_content.graphemes(true).for_each(|grapheme| {
+            let width = grapheme_width(grapheme, render_x);
+            if grapheme == "\t" {
+                (0..width).for_each(|_| row.render.push(' '));
+            } else if let Some(picture) = control_picture(grapheme) {
+                row.render.push_str(&picture);
             } else {
-                row.render.push(c);
+                row.render.push_str(grapheme);
             }
+            render_x += width;
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `a` at column 0, a tab to column 4, `b`, a wide `日` at 5 and 6, `c` at 7
+    fn row() -> Row {
+        Row::new("a\tb日c".into())
+    }
+
+    #[test]
+    fn column_chars_takes_the_graphemes_overlapping() {
+        assert_eq!(row().column_chars(0..1), Some(0..1));
+        assert_eq!(row().column_chars(2..5), Some(1..3));
+        assert_eq!(row().column_chars(6..7), Some(3..4));
+        assert_eq!(row().column_chars(6..20), Some(3..5));
+    }
+
+    #[test]
+    fn column_chars_with_no_columns_is_where_to_insert() {
+        assert_eq!(row().column_chars(2..2), Some(1..1));
+        assert_eq!(row().column_chars(7..7), Some(4..4));
+        assert_eq!(row().column_chars(8..8), Some(5..5));
+        assert_eq!(row().column_chars(9..12), None);
+    }
+
+    #[test]
+    fn column_step_moves_by_grapheme_then_by_column() {
+        let row = row();
+        assert_eq!(
+            [0, 1, 5, 7, 8].map(|it| row.column_step(it, true)),
+            [1, 4, 7, 8, 9]
+        );
+        assert_eq!(
+            [0, 4, 6, 8, 10].map(|it| row.column_step(it, false)),
+            [0, 1, 5, 7, 9]
+        );
+    }
+
+    /// read the bytes from a file and save them again, the buffer read and the bytes written
+    fn round_trip(name: &str, bytes: &[u8]) -> (EditorRows, Vec<u8>) {
+        let dir = std::env::temp_dir().join(format!("rim-test-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("in.txt"), bytes).unwrap();
+        let mut editor_rows =
+            EditorRows::from_file(dir.join("in.txt"), &mut None, u64::MAX).unwrap();
+        let loaded_rows = rows(&editor_rows);
+        editor_rows.save_as(dir.join("out.txt"), false).unwrap();
+        let written = fs::read(dir.join("out.txt")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(rows(&editor_rows), loaded_rows);
+        (editor_rows, written)
+    }
+
+    #[test]
+    fn line_ending_follows_most_lines() {
+        assert!(LineEnding::detect("a\r\nb\r\nc\n") == LineEnding::CrLf);
+        assert!(LineEnding::detect("a\r\nb\nc\n") == LineEnding::Lf);
+        assert!(LineEnding::detect("a") == LineEnding::Lf);
+        assert!(LineEnding::detect("") == LineEnding::Lf);
+    }
+
+    #[test]
+    fn crlf_round_trips() {
+        let (editor_rows, written) = round_trip("crlf", b"one\r\ntwo\r\n");
+        assert!(editor_rows.line_ending == LineEnding::CrLf);
+        assert!(editor_rows.final_newline);
+        assert_eq!(rows(&editor_rows), ["one", "two"]);
+        assert_eq!(written, b"one\r\ntwo\r\n");
+    }
+
+    #[test]
+    fn lf_round_trips() {
+        let (editor_rows, written) = round_trip("lf", b"one\n\ntwo\n");
+        assert!(editor_rows.line_ending == LineEnding::Lf);
+        assert_eq!(rows(&editor_rows), ["one", "", "two"]);
+        assert_eq!(written, b"one\n\ntwo\n");
+    }
+
+    #[test]
+    fn missing_final_newline_round_trips() {
+        let (editor_rows, written) = round_trip("no-final", b"one\r\ntwo");
+        assert!(!editor_rows.final_newline);
+        assert_eq!(rows(&editor_rows), ["one", "two"]);
+        assert_eq!(written, b"one\r\ntwo");
+    }
+
+    #[test]
+    fn empty_file_round_trips() {
+        let (editor_rows, written) = round_trip("empty", b"");
+        assert_eq!(editor_rows.number_of_rows(), 0);
+        assert!(written.is_empty());
+    }
+
+    fn delete(at: usize, text: &str) -> Change {
+        Change::Delete {
+            at,
+            text: text.into(),
+        }
+    }
+
+    fn rows(editor_rows: &EditorRows) -> Vec<String> {
+        editor_rows.buffer.rows().map(Cow::into_owned).collect()
+    }
+
+    #[test]
+    fn deleting_the_last_terminator_keeps_the_last_row() {
+        let mut editor_rows = EditorRows::from_rows(["ab"].into_iter());
+        assert_eq!(editor_rows.apply(delete(2, "\n")), 0..0);
+        assert_eq!(rows(&editor_rows), ["ab"]);
+        editor_rows.apply(delete(1, "b\n"));
+        assert_eq!(rows(&editor_rows), ["a"]);
+        assert_eq!(editor_rows.len_chars(), 2);
+        editor_rows.undo();
+        assert_eq!(rows(&editor_rows), ["ab"]);
+    }
+
+    #[test]
+    fn deleting_whole_rows_at_the_end_is_kept() {
+        let mut editor_rows = EditorRows::from_rows(["one", "two"].into_iter());
+        editor_rows.apply(delete(4, "two\n"));
+        assert_eq!(rows(&editor_rows), ["one"]);
+        editor_rows.apply(delete(0, "one\n"));
+        assert_eq!(editor_rows.number_of_rows(), 0);
+        assert_eq!(editor_rows.len_chars(), 0);
+    }
+
+    #[test]
+    fn inserting_at_the_end_adds_a_terminated_row() {
+        let mut editor_rows = EditorRows::new();
+        editor_rows.apply(Change::Insert {
+            at: 0,
+            text: "one\ntwo".into(),
+        });
+        assert_eq!(rows(&editor_rows), ["one", "two"]);
+        assert_eq!(editor_rows.len_chars(), 8);
+    }
+
+    #[test]
+    fn char_index_and_cursor_position_count_graphemes() {
+        let editor_rows = EditorRows::from_rows(["e\u{301}x", "ab"].into_iter());
+        assert_eq!(editor_rows.char_index(1, 0), 2);
+        assert_eq!(editor_rows.char_index(2, 0), 3);
+        assert_eq!(editor_rows.char_index(1, 1), 5);
+        assert_eq!(editor_rows.char_index(0, 2), 7);
+        assert_eq!(editor_rows.cursor_position(2), (1, 0));
+        // inside a grapheme is past its start
+        assert_eq!(editor_rows.cursor_position(1), (1, 0));
+        assert_eq!(editor_rows.cursor_position(6), (2, 1));
+        assert_eq!(editor_rows.cursor_position(7), (0, 2));
+    }
+
+    #[test]
+    fn word_at_finds_the_word_under_or_before_the_index() {
+        assert_eq!(word_at("let foo_bar = 1;", 4), Some(4..11));
+        assert_eq!(word_at("let foo_bar = 1;", 8), Some(4..11));
+        assert_eq!(word_at("let foo_bar = 1;", 11), Some(4..11));
+        assert_eq!(word_at("let foo_bar = 1;", 14), Some(14..15));
+    }
+
+    #[test]
+    fn word_at_skips_what_is_not_a_word() {
+        assert_eq!(word_at("a  = b", 3), None);
+        assert_eq!(word_at("(x)", 0), None);
+        assert_eq!(word_at("self.foo", 6), Some(5..8));
+        assert_eq!(word_at("self.foo", 4), Some(0..4));
+        assert_eq!(word_at("", 0), None);
+    }
+
+    #[test]
+    fn word_at_counts_graphemes() {
+        assert_eq!(word_at("e\u{301}t\u{e9} x", 1), Some(0..3));
+        assert_eq!(word_at("日本 語", 4), Some(3..4));
+    }
+}
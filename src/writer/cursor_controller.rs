@@ -1,10 +1,11 @@
-use crate::writer::content::{EditorRows, Row};
-use crate::TAB_SIZE;
+use crate::writer::content::{first_non_blank, grapheme_width, word_starts, EditorRows};
 use crossterm::event::KeyCode;
 use std::cmp;
 use std::cmp::Ordering;
+use unicode_segmentation::UnicodeSegmentation;
 
 /// Cursor Controller struct
+#[derive(Clone)]
 pub struct CursorController {
     pub cursor_x: usize,
     pub cursor_y: usize,
@@ -13,6 +14,7 @@ pub struct CursorController {
     pub row_offset: usize,
     pub column_offset: usize,
     pub render_x: usize,
+    pub anchor: Option<(usize, usize)>,
 }
 
 impl CursorController {
@@ -26,6 +28,16 @@ impl CursorController {
             row_offset: 0,
             column_offset: 0,
             render_x: 0,
+            anchor: None,
+        }
+    }
+
+    /// start selecting from the cursor, or stop selecting
+    pub fn select(&mut self, selecting: bool) {
+        if !selecting {
+            self.anchor = None;
+        } else if self.anchor.is_none() {
+            self.anchor = Some((self.cursor_x, self.cursor_y));
         }
     }
 
@@ -33,7 +45,7 @@ impl CursorController {
     pub fn scroll(&mut self, editor_rows: &EditorRows) {
         self.render_x = 0;
         if self.cursor_y < editor_rows.number_of_rows() {
-            self.render_x = self.get_render_x(editor_rows.get_editor_row(self.cursor_y))
+            self.render_x = self.get_render_x(&editor_rows.row_content(self.cursor_y))
         }
         self.row_offset = cmp::min(self.row_offset, self.cursor_y);
         if self.cursor_y >= self.row_offset + self.screen_rows {
@@ -46,24 +58,36 @@ impl CursorController {
     }
 
     /// get render_x
-    pub fn get_render_x(&self, row: &Row) -> usize {
-        row.row_content[..self.cursor_x]
-            .chars()
-            .fold(0, |render_x, c| {
-                if c == '\t' {
-                    render_x + (TAB_SIZE - 1) - (render_x % TAB_SIZE) + 1
-                } else {
-                    render_x + 1
-                }
+    pub fn get_render_x(&self, row_content: &str) -> usize {
+        row_content
+            .graphemes(true)
+            .take(self.cursor_x)
+            .fold(0, |render_x, grapheme| {
+                render_x + grapheme_width(grapheme, render_x)
             })
     }
 
+    /// keep the display column when moving to another row
+    fn move_vertically(&mut self, cursor_y: usize, editor_rows: &EditorRows) {
+        if cursor_y == self.cursor_y {
+            return;
+        }
+        let number_of_rows = editor_rows.number_of_rows();
+        if self.cursor_y < number_of_rows && cursor_y < number_of_rows {
+            let render_x = self.get_render_x(&editor_rows.row_content(self.cursor_y));
+            self.cursor_x = editor_rows
+                .get_editor_row(cursor_y)
+                .get_row_content_x(render_x);
+        }
+        self.cursor_y = cursor_y;
+    }
+
     /// move cursor by arrow keys
     pub fn move_cursor(&mut self, direction: KeyCode, editor_rows: &EditorRows) {
         let number_of_rows = editor_rows.number_of_rows();
         match direction {
             KeyCode::Up => {
-                self.cursor_y = self.cursor_y.saturating_sub(1);
+                self.move_vertically(self.cursor_y.saturating_sub(1), editor_rows);
             }
             // move left at start of a line - to the end of the previous line
             KeyCode::Left => {
@@ -71,21 +95,18 @@ impl CursorController {
                     self.cursor_x -= 1;
                 } else if self.cursor_y > 0 {
                     self.cursor_y -= 1;
-                    self.cursor_x = editor_rows.get_render(self.cursor_y).len();
+                    self.cursor_x = editor_rows.row_len(self.cursor_y);
                 }
             }
             KeyCode::Down => {
                 if self.cursor_y < number_of_rows {
-                    self.cursor_y += 1;
+                    self.move_vertically(self.cursor_y + 1, editor_rows);
                 }
             }
             // move right as end of a line - to the beginning of the next line
             KeyCode::Right => {
                 if self.cursor_y < number_of_rows {
-                    match self
-                        .cursor_x
-                        .cmp(&editor_rows.get_render(self.cursor_y).len())
-                    {
+                    match self.cursor_x.cmp(&editor_rows.row_len(self.cursor_y)) {
                         Ordering::Less => self.cursor_x += 1,
                         Ordering::Equal => {
                             self.cursor_y += 1;
@@ -97,16 +118,99 @@ impl CursorController {
             }
             KeyCode::End => {
                 if self.cursor_y < number_of_rows {
-                    self.cursor_x = editor_rows.get_render(self.cursor_y).len();
+                    self.cursor_x = editor_rows.row_len(self.cursor_y);
                 }
             }
+            // go to the first non-blank char, or to the start of the line if already there
             KeyCode::Home => {
-                self.cursor_x = 0;
+                let first = if self.cursor_y < number_of_rows {
+                    first_non_blank(&editor_rows.row_content(self.cursor_y))
+                } else {
+                    0
+                };
+                self.cursor_x = if self.cursor_x == first { 0 } else { first };
             }
             _ => unimplemented!(),
         }
-        let row_len = if self.cursor_y < number_of_rows {
-            editor_rows.get_render(self.cursor_y).len()
+        self.clamp_cursor_x(editor_rows);
+    }
+
+    /// move cursor by arrow keys with Ctrl - by words, by paragraphs,
+    /// or to the start and the end of the buffer
+    pub fn move_cursor_far(&mut self, direction: KeyCode, editor_rows: &EditorRows) {
+        let number_of_rows = editor_rows.number_of_rows();
+        let is_blank = |y: usize| editor_rows.row_content(y).trim().is_empty();
+        match direction {
+            // start of the previous word, or the end of the previous line
+            KeyCode::Left => {
+                if self.cursor_y >= number_of_rows || self.cursor_x == 0 {
+                    self.move_cursor(KeyCode::Left, editor_rows);
+                    return;
+                }
+                let starts = word_starts(&editor_rows.row_content(self.cursor_y));
+                self.cursor_x = starts
+                    .into_iter()
+                    .rev()
+                    .find(|&start| start < self.cursor_x)
+                    .unwrap_or(0);
+            }
+            // start of the next word, or the start of the next line
+            KeyCode::Right => {
+                if self.cursor_y >= number_of_rows {
+                    return;
+                }
+                let row_len = editor_rows.row_len(self.cursor_y);
+                if self.cursor_x >= row_len {
+                    self.move_cursor(KeyCode::Right, editor_rows);
+                    return;
+                }
+                let starts = word_starts(&editor_rows.row_content(self.cursor_y));
+                self.cursor_x = starts
+                    .into_iter()
+                    .find(|&start| start > self.cursor_x)
+                    .unwrap_or(row_len);
+            }
+            // blank line before the paragraph
+            KeyCode::Up => {
+                let mut y = cmp::min(self.cursor_y, number_of_rows.saturating_sub(1));
+                while y > 0 && is_blank(y) {
+                    y -= 1;
+                }
+                while y > 0 && !is_blank(y) {
+                    y -= 1;
+                }
+                self.cursor_y = y;
+                self.cursor_x = 0;
+            }
+            // blank line after the paragraph
+            KeyCode::Down => {
+                let mut y = self.cursor_y;
+                while y < number_of_rows && is_blank(y) {
+                    y += 1;
+                }
+                while y < number_of_rows && !is_blank(y) {
+                    y += 1;
+                }
+                self.cursor_y = cmp::min(y, number_of_rows.saturating_sub(1));
+                self.cursor_x = 0;
+            }
+            KeyCode::Home => {
+                self.cursor_y = 0;
+                self.cursor_x = 0;
+            }
+            KeyCode::End => {
+                self.cursor_y = number_of_rows.saturating_sub(1);
+                self.cursor_x = usize::MAX;
+            }
+            _ => {}
+        }
+        self.clamp_cursor_x(editor_rows);
+    }
+
+    /// keep the cursor inside its row
+    fn clamp_cursor_x(&mut self, editor_rows: &EditorRows) {
+        let row_len = if self.cursor_y < editor_rows.number_of_rows() {
+            editor_rows.row_len(self.cursor_y)
         } else {
             0
         };
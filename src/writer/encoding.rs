@@ -0,0 +1,295 @@
+use std::io::{self, Error, ErrorKind, Write};
+
+/// Encoding enum - how the text of a file is stored on the disk
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    /// parse the name of an encoding
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "utf-8-bom" | "utf8-bom" => Some(Encoding::Utf8Bom),
+            "utf-16le" | "utf16le" | "utf-16" | "utf16" => Some(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Some(Encoding::Utf16Be),
+            "latin-1" | "latin1" | "iso-8859-1" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+
+    /// name shown in the status bar
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf8Bom => "UTF-8 BOM",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Latin1 => "Latin-1",
+        }
+    }
+
+    /// byte order mark written at the start of the file
+    pub fn bom(&self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8Bom => b"\xEF\xBB\xBF",
+            Encoding::Utf16Le => b"\xFF\xFE",
+            Encoding::Utf16Be => b"\xFE\xFF",
+            Encoding::Utf8 | Encoding::Latin1 => b"",
+        }
+    }
+
+    /// guess the encoding from the byte order mark or the bytes themselves
+    pub fn detect(bytes: &[u8]) -> Self {
+        if let Some(encoding) = [Encoding::Utf8Bom, Encoding::Utf16Le, Encoding::Utf16Be]
+            .into_iter()
+            .find(|encoding| bytes.starts_with(encoding.bom()))
+        {
+            return encoding;
+        }
+        // text in UTF-16 without a byte order mark has a zero in most ASCII code units
+        if bytes.len() >= 2 && bytes.len().is_multiple_of(2) {
+            let zeros_at = |parity| {
+                bytes
+                    .iter()
+                    .skip(parity)
+                    .step_by(2)
+                    .filter(|byte| **byte == 0)
+                    .count()
+            };
+            let units = bytes.len() / 2;
+            if zeros_at(1) * 2 > units && zeros_at(0) == 0 {
+                return Encoding::Utf16Le;
+            }
+            if zeros_at(0) * 2 > units && zeros_at(1) == 0 {
+                return Encoding::Utf16Be;
+            }
+        }
+        if std::str::from_utf8(bytes).is_ok() {
+            Encoding::Utf8
+        } else {
+            Encoding::Latin1
+        }
+    }
+
+    /// decode the bytes, replacing what is invalid in the encoding
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        self.decode_part(bytes.strip_prefix(self.bom()).unwrap_or(bytes))
+    }
+
+    /// decode a part of the file that does not start with the byte order mark
+    pub fn decode_part(&self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => String::from_utf8_lossy(bytes).into_owned(),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let units = bytes.chunks(2).map(|pair| {
+                    let pair = [pair[0], *pair.get(1).unwrap_or(&0)];
+                    if *self == Encoding::Utf16Le {
+                        u16::from_le_bytes(pair)
+                    } else {
+                        u16::from_be_bytes(pair)
+                    }
+                });
+                char::decode_utf16(units)
+                    .map(|ch| ch.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect()
+            }
+            Encoding::Latin1 => bytes.iter().map(|byte| *byte as char).collect(),
+        }
+    }
+
+    /// length of the longest prefix of the bytes that does not end inside a char
+    pub fn complete_len(&self, bytes: &[u8]) -> usize {
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => {
+                let start = bytes.len().saturating_sub(3);
+                for i in (start..bytes.len()).rev() {
+                    let byte = bytes[i];
+                    if byte & 0xC0 != 0x80 {
+                        let len = match byte {
+                            0xF0.. => 4,
+                            0xE0.. => 3,
+                            0xC0.. => 2,
+                            _ => 1,
+                        };
+                        return if bytes.len() - i < len {
+                            i
+                        } else {
+                            bytes.len()
+                        };
+                    }
+                }
+                bytes.len()
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let len = bytes.len() - bytes.len() % 2;
+                let last = match (len, self) {
+                    (0, _) => return 0,
+                    (_, Encoding::Utf16Le) => u16::from_le_bytes([bytes[len - 2], bytes[len - 1]]),
+                    _ => u16::from_be_bytes([bytes[len - 2], bytes[len - 1]]),
+                };
+                if (0xD800..0xDC00).contains(&last) {
+                    len - 2
+                } else {
+                    len
+                }
+            }
+            Encoding::Latin1 => bytes.len(),
+        }
+    }
+
+    /// encode the text
+    pub fn encode(&self, text: &str) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => Ok(text.as_bytes().to_vec()),
+            Encoding::Utf16Le => Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            Encoding::Utf16Be => Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+            Encoding::Latin1 => text
+                .chars()
+                .map(|ch| {
+                    u8::try_from(ch).map_err(|_| {
+                        Error::new(
+                            ErrorKind::InvalidData,
+                            format!("'{}' can't be encoded in {}", ch, self.name()),
+                        )
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Encoded Writer struct - encodes the UTF-8 text written to it
+pub struct EncodedWriter<'a> {
+    out: &'a mut dyn Write,
+    encoding: Encoding,
+    written: usize,
+}
+
+impl<'a> EncodedWriter<'a> {
+    /// constructor - starts with the byte order mark
+    pub fn new(out: &'a mut dyn Write, encoding: Encoding) -> io::Result<Self> {
+        out.write_all(encoding.bom())?;
+        Ok(Self {
+            out,
+            encoding,
+            written: encoding.bom().len(),
+        })
+    }
+
+    /// bytes written to the underlying writer
+    pub fn written(&self) -> usize {
+        self.written
+    }
+}
+
+impl Write for EncodedWriter<'_> {
+    /// impl write() - encode the complete chars of the buffer
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = match std::str::from_utf8(buf) {
+            Ok(text) => text,
+            Err(err) if err.error_len().is_none() && err.valid_up_to() > 0 => {
+                std::str::from_utf8(&buf[..err.valid_up_to()]).unwrap()
+            }
+            Err(err) => return Err(Error::new(ErrorKind::InvalidData, err)),
+        };
+        let bytes = self.encoding.encode(text)?;
+        self.out.write_all(&bytes)?;
+        self.written += bytes.len();
+        Ok(text.len())
+    }
+
+    /// impl flush()
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCODINGS: [Encoding; 5] = [
+        Encoding::Utf8,
+        Encoding::Utf8Bom,
+        Encoding::Utf16Le,
+        Encoding::Utf16Be,
+        Encoding::Latin1,
+    ];
+
+    /// write the text the way a file is saved
+    fn save(text: &str, encoding: Encoding) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut out = EncodedWriter::new(&mut bytes, encoding).unwrap();
+        out.write_all(text.as_bytes()).unwrap();
+        let written = out.written();
+        assert_eq!(written, bytes.len());
+        bytes
+    }
+
+    #[test]
+    fn text_round_trips_in_every_encoding() {
+        for encoding in ENCODINGS {
+            let text = if encoding == Encoding::Latin1 {
+                "caf\u{e9} cr\u{e8}me\n"
+            } else {
+                "caf\u{e9} \u{1f980} \u{4e2d}\n"
+            };
+            let bytes = save(text, encoding);
+            assert_eq!(encoding.decode(&bytes), text, "{}", encoding.name());
+        }
+    }
+
+    #[test]
+    fn detects_what_it_saves() {
+        for encoding in ENCODINGS {
+            let bytes = save("caf\u{e9} au lait\n", encoding);
+            assert_eq!(Encoding::detect(&bytes), encoding, "{}", encoding.name());
+        }
+    }
+
+    #[test]
+    fn detects_utf16_without_bom() {
+        let text: Vec<u8> = "plain text"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        assert_eq!(Encoding::detect(&text), Encoding::Utf16Le);
+        let text: Vec<u8> = "plain text"
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        assert_eq!(Encoding::detect(&text), Encoding::Utf16Be);
+    }
+
+    #[test]
+    fn latin1_can_not_encode_other_chars() {
+        assert!(Encoding::Latin1.encode("\u{20ac}").is_err());
+    }
+
+    #[test]
+    fn complete_len_stops_before_a_cut_char() {
+        let bytes = "a\u{e9}".as_bytes();
+        assert_eq!(Encoding::Utf8.complete_len(&bytes[..2]), 1);
+        assert_eq!(Encoding::Utf8.complete_len(bytes), 3);
+        let units: Vec<u8> = "\u{1f980}"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        assert_eq!(Encoding::Utf16Le.complete_len(&units[..3]), 0);
+        assert_eq!(Encoding::Utf16Le.complete_len(&units), 4);
+    }
+
+    #[test]
+    fn names_parse_back() {
+        for encoding in ENCODINGS {
+            let name = encoding.name().to_lowercase().replace(' ', "-");
+            assert_eq!(Encoding::from_name(&name), Some(encoding));
+        }
+    }
+}
@@ -0,0 +1,393 @@
+use crate::writer::content::LineEnding;
+use crate::writer::encoding::Encoding;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+
+/// Disk State struct - what the file looked like when it was read or written
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub struct DiskState {
+    modified: Option<SystemTime>,
+    len: Option<u64>,
+}
+
+impl DiskState {
+    /// the current state of the file
+    pub fn of(path: &Path) -> Self {
+        fs::metadata(path)
+            .map(|metadata| Self {
+                modified: metadata.modified().ok(),
+                len: Some(metadata.len()),
+            })
+            .unwrap_or_default()
+    }
+
+    /// whether the file exists
+    pub fn exists(&self) -> bool {
+        self.len.is_some()
+    }
+}
+
+/// Loaded File struct - the text of a file and the way it is stored
+pub struct LoadedFile {
+    pub contents: String,
+    pub encoding: Encoding,
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+    pub disk_state: DiskState,
+}
+
+impl LoadedFile {
+    /// the rows without line endings
+    pub fn rows(&self) -> impl Iterator<Item = &str> {
+        self.contents.lines()
+    }
+
+    /// the rows each terminated by '\n'
+    pub fn text(&self) -> String {
+        let mut text = String::with_capacity(self.contents.len() + 1);
+        push_rows(&self.contents, &mut text);
+        text
+    }
+}
+
+/// push the rows of the contents to the text, each terminated by '\n'
+fn push_rows(contents: &str, text: &mut String) {
+    contents.lines().for_each(|row| {
+        text.push_str(row);
+        text.push('\n');
+    });
+}
+
+/// bytes read at once by the loader, and looked at to tell binary files
+const CHUNK_SIZE: usize = 1 << 20;
+const SAMPLE_SIZE: u64 = 8 << 10;
+
+/// Loader struct - reads a large file a chunk of rows at a time
+pub struct Loader {
+    file: File,
+    pub encoding: Encoding,
+    pub line_ending: Option<LineEnding>,
+    pub final_newline: bool,
+    pending: Vec<u8>,
+    rest: String,
+    started: bool,
+}
+
+impl Loader {
+    /// constructor - detects the encoding from the start of the file
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut sample = Vec::new();
+        File::open(path)?
+            .take(SAMPLE_SIZE)
+            .read_to_end(&mut sample)?;
+        Ok(Self {
+            file: File::open(path)?,
+            encoding: detect_sample(&sample),
+            line_ending: None,
+            final_newline: true,
+            pending: Vec::new(),
+            rest: String::new(),
+            started: false,
+        })
+    }
+
+    /// the next complete rows each terminated by '\n', None once the file is read
+    pub fn next_rows(&mut self) -> io::Result<Option<String>> {
+        let mut text = String::new();
+        while text.is_empty() {
+            let start = self.pending.len();
+            self.pending.resize(start + CHUNK_SIZE, 0);
+            let len = self.file.read(&mut self.pending[start..])?;
+            self.pending.truncate(start + len);
+            if !self.started {
+                self.started = true;
+                if self.pending.starts_with(self.encoding.bom()) {
+                    self.pending.drain(..self.encoding.bom().len());
+                }
+            }
+            if len == 0 {
+                self.rest
+                    .push_str(&self.encoding.decode_part(&self.pending));
+                self.pending.clear();
+                if self.rest.is_empty() {
+                    return Ok(None);
+                }
+                self.final_newline = false;
+                push_rows(&std::mem::take(&mut self.rest), &mut text);
+                break;
+            }
+            let complete = self.encoding.complete_len(&self.pending);
+            let decoded = self.encoding.decode_part(&self.pending[..complete]);
+            self.pending.drain(..complete);
+            self.rest.push_str(&decoded);
+            if let Some(end) = self.rest.rfind('\n') {
+                let rest = self.rest.split_off(end + 1);
+                let contents = std::mem::replace(&mut self.rest, rest);
+                self.line_ending
+                    .get_or_insert_with(|| LineEnding::detect(&contents));
+                push_rows(&contents, &mut text);
+            }
+        }
+        Ok(Some(text))
+    }
+}
+
+/// the encoding of a file from its first bytes, which may end inside a char
+fn detect_sample(sample: &[u8]) -> Encoding {
+    match Encoding::detect(sample) {
+        Encoding::Latin1 => Encoding::detect(&sample[..Encoding::Utf8.complete_len(sample)]),
+        encoding => encoding,
+    }
+}
+
+/// whether the start of the file has NUL bytes or mostly control chars and invalid UTF-8,
+/// text in UTF-16 aside
+pub fn looks_binary(path: &Path) -> io::Result<bool> {
+    let mut sample = Vec::new();
+    File::open(path)?
+        .take(SAMPLE_SIZE)
+        .read_to_end(&mut sample)?;
+    if matches!(
+        detect_sample(&sample),
+        Encoding::Utf16Le | Encoding::Utf16Be
+    ) {
+        return Ok(false);
+    }
+    if sample.contains(&0) {
+        return Ok(true);
+    }
+    let controls = sample
+        .iter()
+        .filter(|byte| **byte < 0x20 && !b"\t\n\r\x0c\x1b".contains(byte))
+        .count();
+    let invalid: usize = sample
+        .utf8_chunks()
+        .map(|chunk| chunk.invalid().len())
+        .sum();
+    Ok((controls + invalid) * 10 > sample.len() * 3)
+}
+
+/// read a file from the disk, detecting its encoding unless one is given
+pub fn load(path: &Path, encoding: Option<Encoding>) -> io::Result<LoadedFile> {
+    let disk_state = DiskState::of(path);
+    let bytes = fs::read(path)?;
+    let encoding = encoding.unwrap_or_else(|| Encoding::detect(&bytes));
+    let contents = encoding.decode(&bytes);
+    Ok(LoadedFile {
+        encoding,
+        line_ending: LineEnding::detect(&contents),
+        final_newline: contents.is_empty() || contents.ends_with('\n'),
+        contents,
+        disk_state,
+    })
+}
+
+/// a size in bytes, with an optional `K`, `M` or `G` for KiB, MiB or GiB
+pub fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let (number, shift) = match text.char_indices().last()? {
+        (i, 'k' | 'K') => (&text[..i], 10),
+        (i, 'm' | 'M') => (&text[..i], 20),
+        (i, 'g' | 'G') => (&text[..i], 30),
+        _ => (text, 0),
+    };
+    number.trim().parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+/// the size in the largest unit it is a whole number of
+pub fn format_size(size: u64) -> String {
+    match size {
+        0 => "0 bytes".into(),
+        size if size % (1 << 30) == 0 => format!("{} GiB", size >> 30),
+        size if size % (1 << 20) == 0 => format!("{} MiB", size >> 20),
+        size if size % (1 << 10) == 0 => format!("{} KiB", size >> 10),
+        size => format!("{} bytes", size),
+    }
+}
+
+/// whether the file can be saved: written to if it exists, and replaced from its directory,
+/// where the temporary file is written, checked without opening or creating anything
+pub fn is_writable(path: &Path) -> bool {
+    let path = resolve_symlinks(path);
+    let file_writable = match access_write(&path) {
+        Ok(()) => true,
+        Err(err) => err.kind() == io::ErrorKind::NotFound,
+    };
+    file_writable && access_write(&parent_dir(&path)).is_ok()
+}
+
+/// whether the process may write to the path, as the permissions and the mount tell
+#[cfg(unix)]
+fn access_write(path: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: the path is a valid C string for the duration of the call
+    match unsafe { libc::access(path.as_ptr(), libc::W_OK) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(unix))]
+fn access_write(path: &Path) -> io::Result<()> {
+    match fs::metadata(path)?.permissions().readonly() {
+        false => Ok(()),
+        true => Err(io::ErrorKind::PermissionDenied.into()),
+    }
+}
+
+/// the directory of the file, the current one for a bare file name
+fn parent_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// follow symlinks to the file they point to, even when it does not exist yet
+pub fn resolve_symlinks(path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf();
+    for _ in 0..40 {
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => match fs::read_link(&path) {
+                Ok(target) => {
+                    path = match path.parent() {
+                        Some(parent) => parent.join(target),
+                        None => target,
+                    }
+                }
+                Err(_) => break,
+            },
+            _ => break,
+        }
+    }
+    path
+}
+
+/// the path with a suffix appended to the file name
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// write through a synced temporary file renamed over the original,
+/// keeping its mode and owner and, if asked, a copy of it as `file~`
+pub fn write_atomically<F>(path: &Path, backup: bool, write: F) -> io::Result<usize>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<usize>,
+{
+    let path = resolve_symlinks(path);
+    let metadata = fs::metadata(&path).ok();
+    let dir = parent_dir(&path);
+    let temp_name = format!(
+        ".{}.{}.tmp",
+        path.file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default(),
+        process::id()
+    );
+    let temp_path = dir.join(temp_name);
+    let result = write_temp_file(&temp_path, metadata.as_ref(), write).and_then(|len| {
+        if backup && metadata.is_some() {
+            fs::copy(&path, with_suffix(&path, "~"))?;
+        }
+        fs::rename(&temp_path, &path)?;
+        sync_dir(&dir);
+        Ok(len)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// create and fill the temporary file with the original permissions
+fn write_temp_file<F>(path: &Path, metadata: Option<&fs::Metadata>, write: F) -> io::Result<usize>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<usize>,
+{
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
+    if let Some(metadata) = metadata {
+        file.set_permissions(metadata.permissions())?;
+        keep_owner(&file, metadata);
+    }
+    let mut out = BufWriter::new(file);
+    let len = write(&mut out)?;
+    let file = out.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    Ok(len)
+}
+
+/// give the file the original owner, which may not be allowed and is then skipped
+#[cfg(unix)]
+fn keep_owner(file: &File, metadata: &fs::Metadata) {
+    use std::os::unix::fs::{fchown, MetadataExt};
+    if fchown(file, Some(metadata.uid()), Some(metadata.gid())).is_err() {
+        let _ = fchown(file, None, Some(metadata.gid()));
+    }
+}
+
+#[cfg(not(unix))]
+fn keep_owner(_file: &File, _metadata: &fs::Metadata) {}
+
+/// make the rename durable
+fn sync_dir(dir: &Path) {
+    if cfg!(unix) {
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_size_takes_bytes_and_units() {
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("512k"), Some(512 << 10));
+        assert_eq!(parse_size(" 64M "), Some(64 << 20));
+        assert_eq!(parse_size("2G"), Some(2 << 30));
+    }
+
+    #[test]
+    fn parse_size_rejects_the_rest() {
+        assert_eq!(parse_size(""), None);
+        assert_eq!(parse_size("M"), None);
+        assert_eq!(parse_size("-1"), None);
+        assert_eq!(parse_size("1.5M"), None);
+        assert_eq!(parse_size("99999999999999999999G"), None);
+    }
+
+    #[test]
+    fn is_writable_leaves_the_directory_alone() {
+        let dir = std::env::temp_dir().join(format!("rim-writable-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("file"), "text").unwrap();
+        assert!(is_writable(&dir.join("file")));
+        assert!(is_writable(&dir.join("new")));
+        assert!(!is_writable(&dir.join("missing").join("new")));
+        let entries: Vec<_> = fs::read_dir(&dir).unwrap().flatten().collect();
+        assert_eq!(entries.len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn format_size_picks_the_unit() {
+        assert_eq!(format_size(0), "0 bytes");
+        assert_eq!(format_size(1000), "1000 bytes");
+        assert_eq!(format_size(512 << 10), "512 KiB");
+        assert_eq!(format_size(64 << 20), "64 MiB");
+        assert_eq!(format_size(1 << 30), "1 GiB");
+    }
+}
@@ -0,0 +1,27 @@
+use ignore::WalkBuilder;
+use std::path::{Path, PathBuf};
+
+/// directories never listed, whether ignored or not
+const SKIPPED_DIRS: [&str; 2] = [".git", "target"];
+
+/// the files under the directory relative to it, sorted, skipping what `.gitignore` ignores
+pub fn project_files(dir: &Path) -> Vec<PathBuf> {
+    let walk = WalkBuilder::new(dir)
+        .require_git(false)
+        .hidden(false)
+        .filter_entry(|entry| {
+            !entry.file_type().is_some_and(|it| it.is_dir())
+                || !SKIPPED_DIRS.iter().any(|it| entry.file_name() == *it)
+        })
+        .build();
+    let mut files: Vec<PathBuf> = walk
+        .flatten()
+        .filter(|entry| entry.file_type().is_some_and(|it| it.is_file()))
+        .map(|entry| {
+            let path = entry.path();
+            path.strip_prefix(dir).unwrap_or(path).to_path_buf()
+        })
+        .collect();
+    files.sort();
+    files
+}
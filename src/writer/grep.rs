@@ -0,0 +1,113 @@
+use crate::writer::file;
+use crate::writer::highlight::SyntaxHighlight;
+use crate::writer::search::Matcher;
+use ignore::WalkBuilder;
+use std::cmp;
+use std::path::{Path, PathBuf};
+
+/// Grep Match struct - where a pattern was found in a file, the line and column from 0
+pub struct GrepMatch {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+}
+
+impl GrepMatch {
+    /// the row listing the match - `path:line: text`
+    pub fn row(&self) -> String {
+        format!("{}:{}: {}", self.path.display(), self.line + 1, self.text)
+    }
+}
+
+/// Grep struct - the matches of a pattern in the files under a directory
+pub struct Grep {
+    pub pattern: String,
+    pub matches: Vec<GrepMatch>,
+    pub files: usize,
+    pub selected: usize,
+}
+
+impl Grep {
+    /// search the text files under the directory, skipping what `.gitignore` ignores,
+    /// files of the large size or more are read and searched a chunk of rows at a time
+    pub fn run(
+        dir: &Path,
+        pattern: &str,
+        matcher: &Matcher,
+        syntax_highlight: &dyn SyntaxHighlight,
+        large_file_size: u64,
+    ) -> Self {
+        let mut grep = Self {
+            pattern: pattern.into(),
+            matches: Vec::new(),
+            files: 0,
+            selected: 0,
+        };
+        let walk = WalkBuilder::new(dir).require_git(false).build();
+        for entry in walk.flatten() {
+            let path = entry.path();
+            if !entry.file_type().is_some_and(|it| it.is_file())
+                || file::looks_binary(path).unwrap_or(true)
+            {
+                continue;
+            }
+            let large = entry
+                .metadata()
+                .is_ok_and(|metadata| metadata.len() >= large_file_size);
+            let before = grep.matches.len();
+            let relative = path.strip_prefix(dir).unwrap_or(path);
+            if large {
+                let Ok(mut loader) = file::Loader::open(path) else {
+                    continue;
+                };
+                let mut line = 0;
+                while let Ok(Some(text)) = loader.next_rows() {
+                    grep.find_in(relative, &text, line, matcher, syntax_highlight);
+                    line += text.matches('\n').count();
+                }
+            } else {
+                let Ok(loaded) = file::load(path, None) else {
+                    continue;
+                };
+                grep.find_in(relative, &loaded.text(), 0, matcher, syntax_highlight);
+            }
+            if grep.matches.len() > before {
+                grep.files += 1;
+            }
+        }
+        grep
+    }
+
+    /// collect the matches in the text of one file, or of its rows from the first line
+    fn find_in(
+        &mut self,
+        path: &Path,
+        text: &str,
+        first_line: usize,
+        matcher: &Matcher,
+        syntax_highlight: &dyn SyntaxHighlight,
+    ) {
+        // newlines are counted up to `counted`, matches looked for from `bytes`
+        let (mut line, mut line_start, mut counted, mut bytes) = (first_line, 0, 0, 0);
+        while let Some(captures) = matcher.captures_at(text, bytes, syntax_highlight) {
+            let found = captures.get(0).unwrap();
+            for (i, _) in text[counted..found.start()].match_indices('\n') {
+                line += 1;
+                line_start = counted + i + 1;
+            }
+            counted = found.start();
+            let line_end = text[line_start..]
+                .find('\n')
+                .map_or(text.len(), |end| line_start + end);
+            self.matches.push(GrepMatch {
+                path: path.to_path_buf(),
+                line,
+                column: text[line_start..found.start()].chars().count(),
+                text: text[line_start..line_end].into(),
+            });
+            // one result per line is enough to get there
+            bytes = cmp::max(found.end(), line_end);
+        }
+    }
+}
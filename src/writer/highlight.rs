@@ -1,7 +1,10 @@
-use crate::writer::content::{EditorContents, Row};
-use crate::{CHAR_STRING_COLOR, COMMENT_COLOR, DEFAULT_COLOR, KEYWORDS_COLOR, NUMBER_COLOR};
+use crate::writer::content::{EditorContents, EditorRows, Row};
+use crate::{
+    BACKGROUND_COLOR, BRACKET_COLOR, CHAR_STRING_COLOR, COMMENT_COLOR, CURSOR_COLOR, DEFAULT_COLOR,
+    KEYWORDS_COLOR, MATCH_COLOR, NUMBER_COLOR, SELECTION_COLOR, TAB_SIZE,
+};
 use crossterm::queue;
-use crossterm::style::{Color, SetForegroundColor};
+use crossterm::style::{Color, SetBackgroundColor, SetForegroundColor};
 use std::cmp;
 
 /// Highlight Type enum
@@ -13,9 +16,94 @@ pub enum HighlightType {
     CharLiteral,
     Comment,
     MultilineComment,
+    Match,
+    Bracket,
+    Selection,
+    Cursor,
     Other(Color),
 }
 
+impl HighlightType {
+    /// whether the text is code, not in a string or a comment
+    pub fn is_code(&self) -> bool {
+        !matches!(
+            self,
+            HighlightType::String
+                | HighlightType::CharLiteral
+                | HighlightType::Comment
+                | HighlightType::MultilineComment
+        )
+    }
+}
+
+/// Indent enum - what one level of indentation is made of
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Indent {
+    Tab,
+    Spaces(usize),
+}
+
+impl Indent {
+    /// one level of indentation
+    pub fn unit(&self) -> String {
+        match self {
+            Indent::Tab => "\t".into(),
+            Indent::Spaces(width) => " ".repeat(*width),
+        }
+    }
+
+    /// the level most rows are indented by, tabs if more rows start with one than with spaces,
+    /// otherwise the step in spaces most often taken from a row to the next, None if no row is
+    pub fn detect<S: AsRef<str>>(rows: impl Iterator<Item = S>) -> Option<Self> {
+        let (mut tabs, mut spaced) = (0, 0);
+        let mut steps = [0usize; 9];
+        let mut previous = 0;
+        for row in rows {
+            let row = row.as_ref();
+            if row.trim().is_empty() {
+                continue;
+            }
+            if row.starts_with('\t') {
+                tabs += 1;
+                continue;
+            }
+            let spaces = row.chars().take_while(|c| *c == ' ').count();
+            if spaces > 0 {
+                spaced += 1;
+            }
+            if let Some(step) = steps.get_mut(spaces.saturating_sub(previous)) {
+                *step += 1;
+            }
+            previous = spaces;
+        }
+        if tabs > spaced {
+            return Some(Indent::Tab);
+        }
+        // the smaller step wins a tie, 2 over 4 when rows go in by both as often
+        (1..steps.len())
+            .filter(|it| steps[*it] > 0)
+            .max_by_key(|it| (steps[*it], cmp::Reverse(*it)))
+            .map(Indent::Spaces)
+    }
+
+    /// number of chars at the end of the indentation making its last level
+    pub fn last_level(&self, indentation: &str) -> usize {
+        let width = match self {
+            Indent::Tab => TAB_SIZE,
+            Indent::Spaces(width) => *width,
+        };
+        match indentation.chars().next_back() {
+            Some('\t') => 1,
+            _ => indentation
+                .chars()
+                .rev()
+                .take_while(|c| *c == ' ')
+                .take(width)
+                .count(),
+        }
+    }
+}
+
 /// Syntax Highlight trait
 pub trait SyntaxHighlight {
     /// extensions
@@ -27,19 +115,52 @@ pub trait SyntaxHighlight {
     /// comment
     fn comment_start(&self) -> &str;
 
+    /// keywords introducing the items listed in the outline
+    fn symbol_keywords(&self) -> &[&str];
+
+    /// one level of indentation, None to follow the indentation of the file
+    fn indent(&self) -> Option<Indent>;
+
     /// multiline comment
     fn multiline_comment(&self) -> Option<(&str, &str)>;
 
     /// syntax color
     fn syntax_color(&self, highlight_type: &HighlightType) -> Color;
 
-    /// update syntax
-    fn update_syntax(&self, at: usize, editor_rows: &mut Vec<Row>);
+    /// highlight one row starting in or out of a multiline comment
+    fn highlight_row(&self, row: &mut Row, in_comment: bool);
 
-    /// color row
+    /// update syntax - carry the multiline comment state down while it changes
+    fn update_syntax(&self, at: usize, editor_rows: &mut EditorRows) {
+        let mut at = at;
+        while at < editor_rows.number_of_rows() {
+            let mut row = editor_rows.get_editor_row(at);
+            self.highlight_row(&mut row, at > 0 && editor_rows.is_comment(at - 1));
+            let changed = editor_rows.is_comment(at) != row.is_comment;
+            editor_rows.set_comment(at, row.is_comment);
+            if !changed {
+                break;
+            }
+            at += 1;
+        }
+    }
+
+    /// color row - overlays such as search matches get a background of their own
     fn color_row(&self, render: &str, highlight: &[HighlightType], out: &mut EditorContents) {
         let mut current_color = self.syntax_color(&HighlightType::Normal);
+        let mut current_background = BACKGROUND_COLOR;
         render.char_indices().for_each(|(i, c)| {
+            let background = match highlight[i] {
+                HighlightType::Match => MATCH_COLOR,
+                HighlightType::Bracket => BRACKET_COLOR,
+                HighlightType::Selection => SELECTION_COLOR,
+                HighlightType::Cursor => CURSOR_COLOR,
+                _ => BACKGROUND_COLOR,
+            };
+            if current_background != background {
+                current_background = background;
+                let _ = queue!(out, SetBackgroundColor(Color::from(background)));
+            }
             let color = self.syntax_color(&highlight[i]);
             if current_color != color {
                 current_color = color;
@@ -48,6 +169,9 @@ pub trait SyntaxHighlight {
             out.push(c);
         });
         let _ = queue!(out, SetForegroundColor(Color::Reset));
+        if current_background != BACKGROUND_COLOR {
+            let _ = queue!(out, SetBackgroundColor(Color::from(BACKGROUND_COLOR)));
+        }
     }
 
     /// is separator
@@ -61,6 +185,12 @@ pub trait SyntaxHighlight {
     }
 }
 
+/// whether the text after a quote makes it a lifetime or a label, `'a` and not `'a'`
+fn is_lifetime(after: &str) -> bool {
+    let mut chars = after.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_') && chars.next() != Some('\'')
+}
+
 #[macro_export]
 macro_rules! syntax_struct {
     (
@@ -72,6 +202,8 @@ macro_rules! syntax_struct {
                 $([$color:expr; $($words:expr), *]),*
             },
             multiline_comment: $ml_comment:expr,
+            symbols: $symbols:expr,
+            indent: $indent:expr,
         }
     ) => {
         pub struct $Name {
@@ -79,6 +211,14 @@ macro_rules! syntax_struct {
             file_type: &'static str,
             comment_start: &'static str,
             multiline_comment: Option<(&'static str, &'static str)>,
+            symbol_keywords: &'static [&'static str],
+            indent: Option<Indent>,
+        }
+
+        impl Default for $Name {
+            fn default() -> Self {
+                Self::new()
+            }
         }
 
         impl $Name {
@@ -88,6 +228,8 @@ macro_rules! syntax_struct {
                     file_type: $type,
                     comment_start: $start,
                     multiline_comment: $ml_comment,
+                    symbol_keywords: &$symbols,
+                    indent: $indent,
                 }
             }
         }
@@ -109,19 +251,27 @@ macro_rules! syntax_struct {
                 self.multiline_comment
             }
 
+            fn symbol_keywords(&self) -> &[&str] {
+                self.symbol_keywords
+            }
+
+            fn indent(&self) -> Option<Indent> {
+                self.indent
+            }
+
             fn syntax_color(&self, highlight_type: &HighlightType) -> Color {
                 match highlight_type {
                     HighlightType::Number => Color::from(NUMBER_COLOR),
                     HighlightType::Normal => Color::from(DEFAULT_COLOR),
                     HighlightType::String | HighlightType::CharLiteral => Color::from(CHAR_STRING_COLOR),
                     HighlightType::Comment | HighlightType::MultilineComment => Color::from(COMMENT_COLOR),
+                    HighlightType::Match | HighlightType::Cursor => Color::from(BACKGROUND_COLOR),
+                    HighlightType::Bracket | HighlightType::Selection => Color::from(DEFAULT_COLOR),
                     HighlightType::Other(color) => *color,
                 }
             }
 
-            fn update_syntax(&self, at: usize, editor_rows: &mut Vec<Row>) {
-                let mut in_comment = at > 0 && editor_rows[at - 1].is_comment;
-                let current_row = &mut editor_rows[at];
+            fn highlight_row(&self, current_row: &mut Row, mut in_comment: bool) {
 
                 macro_rules! add {
                     ($h:expr) => {
@@ -196,6 +346,11 @@ macro_rules! syntax_struct {
                         i += 1;
                         previous_separator = true;
                         continue;
+                    } else if c == '\'' && is_lifetime(&current_row.render[i + 1..]) {
+                        add!(HighlightType::Normal);
+                        i += 1;
+                        previous_separator = false;
+                        continue;
                     } else if c == '"' || c == '\'' {
                         in_string = Some(c);
                         add! {
@@ -235,16 +390,25 @@ macro_rules! syntax_struct {
                     i += 1;
                 }
                 assert_eq!(current_row.render.len(), current_row.highlight.len());
-                let changed = current_row.is_comment != in_comment;
                 current_row.is_comment = in_comment;
-                if changed && at + 1 < editor_rows.len() {
-                    self.update_syntax(at + 1, editor_rows)
-                }
             }
         }
     };
 }
 
+syntax_struct! {
+    // plain text, only colored where overlays like search matches are drawn
+    pub struct PlainHighlight {
+        extensions: [],
+        file_type: "no ft",
+        comment_start: "",
+        keywords: {},
+        multiline_comment: None::<(&str, &str)>,
+        symbols: [],
+        indent: None,
+    }
+}
+
 syntax_struct! {
     // rust highlight
     pub struct RustHighlight {
@@ -255,7 +419,8 @@ syntax_struct! {
             [Color::from(KEYWORDS_COLOR);
                 "pub", "mod", "unsafe", "extern", "crate", "use", "type", "struct", "enum", "union",
                 "const", "static", "mut", "let", "if", "else", "impl", "trait", "for", "fn", "self",
-                "Self", "while", "true", "false", "in", "continue", "break", "loop", "match"
+                "Self", "while", "true", "false", "in", "continue", "break", "loop", "match",
+                "macro_rules!"
             ],
             [Color::from(KEYWORDS_COLOR);
                 "isize", "i8", "i16", "i32", "i64", "usize", "u8", "u16", "u32", "u64", "f32",
@@ -263,5 +428,75 @@ syntax_struct! {
             ]
         },
         multiline_comment: Some(("/*", "*/")),
+        symbols: ["fn", "struct", "enum", "trait", "impl", "mod", "const", "macro_rules!"],
+        indent: Some(Indent::Spaces(4)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the code of the row as highlighted, strings, chars and comments left out
+    fn code(row: &str) -> String {
+        let mut row = Row::new(row.into());
+        RustHighlight::new().highlight_row(&mut row, false);
+        row.render
+            .bytes()
+            .zip(&row.highlight)
+            .filter(|(_, highlight)| highlight.is_code())
+            .map(|(c, _)| c as char)
+            .collect()
+    }
+
+    #[test]
+    fn detect_takes_the_most_common_step() {
+        let rows = ["fn f() {", "  if x {", "    y();", "  }", "  z();", "}"];
+        assert_eq!(Indent::detect(rows.iter()), Some(Indent::Spaces(2)));
+        let rows = ["a:", "    b:", "        c", "    d:", "        e", "f"];
+        assert_eq!(Indent::detect(rows.iter()), Some(Indent::Spaces(4)));
+    }
+
+    #[test]
+    fn detect_prefers_tabs_used_by_more_rows() {
+        let rows = ["{", "\tone", "\ttwo", "\t\tthree", "   aligned", "}"];
+        assert_eq!(Indent::detect(rows.iter()), Some(Indent::Tab));
+    }
+
+    #[test]
+    fn detect_finds_nothing_without_indentation() {
+        assert_eq!(Indent::detect(["one", "", "  ", "two"].iter()), None);
+        assert_eq!(Indent::detect([""; 0].iter()), None);
+    }
+
+    #[test]
+    fn last_level_takes_one_tab_or_up_to_a_level_of_spaces() {
+        assert_eq!(Indent::Tab.last_level("\t\t"), 1);
+        assert_eq!(Indent::Tab.last_level("\t      "), 4);
+        assert_eq!(Indent::Spaces(4).last_level("      "), 4);
+        assert_eq!(Indent::Spaces(4).last_level("\t  "), 2);
+        assert_eq!(Indent::Spaces(2).last_level("    \t"), 1);
+        assert_eq!(Indent::Spaces(4).last_level(""), 0);
+    }
+
+    #[test]
+    fn lifetimes_are_not_char_literals() {
+        assert_eq!(
+            code("    pub fn text(&self) -> Cow<'_, str> {"),
+            "    pub fn text(&self) -> Cow<'_, str> {"
+        );
+        assert_eq!(
+            code("fn f<'a>(x: &'a str) -> &'static str {"),
+            "fn f<'a>(x: &'a str) -> &'static str {"
+        );
+        assert_eq!(code("'outer: loop {"), "'outer: loop {");
+    }
+
+    #[test]
+    fn char_literals_are_still_found() {
+        assert_eq!(code("let c = 'a'; {"), "let c = ; {");
+        assert_eq!(code("let c = '_'; {"), "let c = ; {");
+        assert_eq!(code("let c = '\\''; {"), "let c = ; {");
+        assert_eq!(code("let c = '{'; }"), "let c = ; }");
     }
 }
@@ -0,0 +1,290 @@
+use crate::writer::content::LineEnding;
+use crate::writer::encoding::Encoding;
+
+/// Change enum - one edit of the buffer, positions are char indexes
+#[derive(Clone)]
+pub enum Change {
+    Insert { at: usize, text: String },
+    Delete { at: usize, text: String },
+    LineEnding { from: LineEnding, to: LineEnding },
+    Encoding { from: Encoding, to: Encoding },
+}
+
+impl Change {
+    /// the change that reverts this one
+    pub fn inverse(&self) -> Self {
+        match self {
+            Change::Insert { at, text } => Change::Delete {
+                at: *at,
+                text: text.clone(),
+            },
+            Change::Delete { at, text } => Change::Insert {
+                at: *at,
+                text: text.clone(),
+            },
+            Change::LineEnding { from, to } => Change::LineEnding {
+                from: *to,
+                to: *from,
+            },
+            Change::Encoding { from, to } => Change::Encoding {
+                from: *to,
+                to: *from,
+            },
+        }
+    }
+}
+
+/// Step struct - the changes undone or redone at once
+struct Step {
+    changes: Vec<Change>,
+    cursor_before: (usize, usize),
+    cursor_after: (usize, usize),
+    typing: bool,
+    id: u64,
+}
+
+/// History struct
+#[derive(Default)]
+pub struct History {
+    undo_stack: Vec<Step>,
+    redo_stack: Vec<Step>,
+    current: Option<Step>,
+    next_id: u64,
+    saved_id: u64,
+    sealed: bool,
+    changes: u64,
+}
+
+impl History {
+    /// constructor
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// open a step for the changes of one command,
+    /// typing continues the previous typing step if the cursor has not moved since
+    pub fn begin_step(&mut self, cursor: (usize, usize), typing: bool) {
+        self.end_step(cursor);
+        let continues_typing = typing
+            && !self.sealed
+            && self
+                .undo_stack
+                .last()
+                .is_some_and(|step| step.typing && step.cursor_after == cursor);
+        self.sealed = false;
+        self.current = if continues_typing {
+            self.undo_stack.pop()
+        } else {
+            self.next_id += 1;
+            Some(Step {
+                changes: Vec::new(),
+                cursor_before: cursor,
+                cursor_after: cursor,
+                typing,
+                id: self.next_id,
+            })
+        };
+    }
+
+    /// close the open step
+    pub fn end_step(&mut self, cursor: (usize, usize)) {
+        if let Some(mut step) = self.current.take() {
+            if !step.changes.is_empty() {
+                step.cursor_after = cursor;
+                self.undo_stack.push(step);
+            }
+        }
+    }
+
+    /// record a change into the open step, or into a step of its own
+    pub fn record(&mut self, change: Change, cursor: (usize, usize)) {
+        self.redo_stack.clear();
+        self.changes += 1;
+        match self.current.as_mut() {
+            Some(step) => step.changes.push(change),
+            None => {
+                self.begin_step(cursor, false);
+                self.record(change, cursor);
+                self.end_step(cursor);
+            }
+        }
+    }
+
+    /// the changes reverting the last step, in order, and the cursor to restore
+    pub fn undo(&mut self) -> Option<(Vec<Change>, (usize, usize))> {
+        self.sealed = true;
+        let step = self.undo_stack.pop()?;
+        self.changes += 1;
+        let changes = step.changes.iter().rev().map(Change::inverse).collect();
+        let cursor = step.cursor_before;
+        self.redo_stack.push(step);
+        Some((changes, cursor))
+    }
+
+    /// the changes of the last undone step and the cursor to restore
+    pub fn redo(&mut self) -> Option<(Vec<Change>, (usize, usize))> {
+        self.sealed = true;
+        let step = self.redo_stack.pop()?;
+        self.changes += 1;
+        let changes = step.changes.clone();
+        let cursor = step.cursor_after;
+        self.undo_stack.push(step);
+        Some((changes, cursor))
+    }
+
+    /// remember the current position as the one on disk
+    pub fn mark_saved(&mut self) {
+        self.sealed = true;
+        self.saved_id = self.position();
+    }
+
+    /// whether the buffer differs from the one on disk
+    pub fn is_modified(&self) -> bool {
+        self.position() != self.saved_id
+    }
+
+    /// number of changes made so far, undo and redo included
+    pub fn changes(&self) -> u64 {
+        self.changes
+    }
+
+    /// id of the last step applied
+    fn position(&self) -> u64 {
+        self.undo_stack.last().map(|step| step.id).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(at: usize, text: &str) -> Change {
+        Change::Insert {
+            at,
+            text: text.into(),
+        }
+    }
+
+    /// type the text at the cursor a char per step, as the editor does
+    fn type_text(history: &mut History, (x, y): (usize, usize), text: &str) {
+        for (i, ch) in text.chars().enumerate() {
+            history.begin_step((x + i, y), true);
+            history.record(insert(x + i, &ch.to_string()), (x + i, y));
+            history.end_step((x + i + 1, y));
+        }
+    }
+
+    fn inserted(changes: &[Change]) -> Vec<(usize, String, bool)> {
+        changes
+            .iter()
+            .map(|change| match change {
+                Change::Insert { at, text } => (*at, text.clone(), true),
+                Change::Delete { at, text } => (*at, text.clone(), false),
+                _ => panic!("not a text change"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn typing_is_undone_at_once() {
+        let mut history = History::new();
+        type_text(&mut history, (0, 0), "abc");
+        let (changes, cursor) = history.undo().unwrap();
+        assert_eq!(
+            inserted(&changes),
+            [
+                (2, "c".into(), false),
+                (1, "b".into(), false),
+                (0, "a".into(), false)
+            ]
+        );
+        assert_eq!(cursor, (0, 0));
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn moving_the_cursor_starts_a_new_typing_step() {
+        let mut history = History::new();
+        type_text(&mut history, (0, 0), "ab");
+        type_text(&mut history, (5, 0), "cd");
+        let (changes, cursor) = history.undo().unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(cursor, (5, 0));
+        assert_eq!(history.undo().unwrap().1, (0, 0));
+    }
+
+    #[test]
+    fn other_edits_are_steps_of_their_own() {
+        let mut history = History::new();
+        type_text(&mut history, (0, 0), "ab");
+        history.begin_step((2, 0), false);
+        history.record(insert(2, "\n"), (2, 0));
+        history.end_step((0, 1));
+        type_text(&mut history, (0, 1), "c");
+        assert_eq!(history.undo().unwrap().0.len(), 1);
+        assert_eq!(
+            inserted(&history.undo().unwrap().0),
+            [(2, "\n".into(), false)]
+        );
+        assert_eq!(history.undo().unwrap().0.len(), 2);
+    }
+
+    #[test]
+    fn typing_after_undo_does_not_join_the_redone_step() {
+        let mut history = History::new();
+        type_text(&mut history, (0, 0), "ab");
+        history.undo();
+        history.redo();
+        type_text(&mut history, (2, 0), "c");
+        assert_eq!(history.undo().unwrap().0.len(), 1);
+    }
+
+    #[test]
+    fn redo_applies_the_undone_changes_again() {
+        let mut history = History::new();
+        type_text(&mut history, (0, 0), "ab");
+        history.undo();
+        let (changes, cursor) = history.redo().unwrap();
+        assert_eq!(
+            inserted(&changes),
+            [(0, "a".into(), true), (1, "b".into(), true)]
+        );
+        assert_eq!(cursor, (2, 0));
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn a_new_change_drops_the_redo_steps() {
+        let mut history = History::new();
+        type_text(&mut history, (0, 0), "ab");
+        history.undo();
+        history.record(insert(0, "x"), (0, 0));
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn modified_until_back_at_the_saved_step() {
+        let mut history = History::new();
+        assert!(!history.is_modified());
+        type_text(&mut history, (0, 0), "a");
+        assert!(history.is_modified());
+        history.mark_saved();
+        assert!(!history.is_modified());
+        type_text(&mut history, (1, 0), "b");
+        assert!(history.is_modified());
+        history.undo();
+        assert!(!history.is_modified());
+        history.undo();
+        assert!(history.is_modified());
+    }
+
+    #[test]
+    fn changes_count_undo_and_redo() {
+        let mut history = History::new();
+        type_text(&mut history, (0, 0), "ab");
+        assert_eq!(history.changes(), 2);
+        history.undo();
+        history.redo();
+        assert_eq!(history.changes(), 4);
+    }
+}
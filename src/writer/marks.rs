@@ -0,0 +1,156 @@
+use crate::writer::history::Change;
+use std::collections::BTreeMap;
+
+/// Marks struct - named marks and the jump list, positions are char indexes
+#[derive(Default)]
+pub struct Marks {
+    named: BTreeMap<char, usize>,
+    jumps: Vec<usize>,
+    jump_index: usize,
+}
+
+impl Marks {
+    /// constructor
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// set the named mark at the char index
+    pub fn set(&mut self, name: char, at: usize) {
+        self.named.insert(name, at);
+    }
+
+    /// the char index of the named mark
+    pub fn get(&self, name: char) -> Option<usize> {
+        self.named.get(&name).copied()
+    }
+
+    /// the named marks in order of their names
+    pub fn list(&self) -> impl Iterator<Item = (char, usize)> + '_ {
+        self.named.iter().map(|(name, at)| (*name, *at))
+    }
+
+    /// remember the position left by a big move, dropping the jumps gone back over
+    pub fn push_jump(&mut self, at: usize) {
+        self.jumps.truncate(self.jump_index);
+        if self.jumps.last() != Some(&at) {
+            self.jumps.push(at);
+        }
+        self.jump_index = self.jumps.len();
+    }
+
+    /// the position before the current one in the jump list
+    pub fn back(&mut self, current: usize) -> Option<usize> {
+        if self.jump_index == 0 {
+            return None;
+        }
+        if self.jump_index == self.jumps.len() {
+            self.jumps.push(current);
+        }
+        self.jump_index -= 1;
+        Some(self.jumps[self.jump_index])
+    }
+
+    /// the position after the current one in the jump list
+    pub fn forward(&mut self) -> Option<usize> {
+        if self.jump_index + 1 >= self.jumps.len() {
+            return None;
+        }
+        self.jump_index += 1;
+        Some(self.jumps[self.jump_index])
+    }
+
+    /// move the positions after the change along with the text
+    pub fn shift(&mut self, change: &Change) {
+        let shift: Box<dyn Fn(usize) -> usize> = match change {
+            Change::Insert { at, text } => {
+                let (at, len) = (*at, text.chars().count());
+                Box::new(move |pos| if pos >= at { pos + len } else { pos })
+            }
+            Change::Delete { at, text } => {
+                let (at, len) = (*at, text.chars().count());
+                Box::new(move |pos| {
+                    if pos > at {
+                        pos - (pos - at).min(len)
+                    } else {
+                        pos
+                    }
+                })
+            }
+            Change::LineEnding { .. } | Change::Encoding { .. } => return,
+        };
+        self.named.values_mut().for_each(|pos| *pos = shift(*pos));
+        self.jumps.iter_mut().for_each(|pos| *pos = shift(*pos));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::content::LineEnding;
+
+    fn insert(at: usize, text: &str) -> Change {
+        Change::Insert {
+            at,
+            text: text.into(),
+        }
+    }
+
+    fn delete(at: usize, text: &str) -> Change {
+        Change::Delete {
+            at,
+            text: text.into(),
+        }
+    }
+
+    #[test]
+    fn insert_moves_the_marks_at_and_after_it() {
+        let mut marks = Marks::new();
+        marks.set('a', 2);
+        marks.set('b', 5);
+        marks.set('c', 9);
+        marks.shift(&insert(5, "héé"));
+        assert_eq!(
+            marks.list().collect::<Vec<_>>(),
+            [('a', 2), ('b', 8), ('c', 12)]
+        );
+    }
+
+    #[test]
+    fn delete_pulls_the_marks_inside_to_its_start() {
+        let mut marks = Marks::new();
+        marks.set('a', 3);
+        marks.set('b', 4);
+        marks.set('c', 6);
+        marks.set('d', 10);
+        marks.shift(&delete(3, "abcd"));
+        assert_eq!(
+            marks.list().collect::<Vec<_>>(),
+            [('a', 3), ('b', 3), ('c', 3), ('d', 6)]
+        );
+    }
+
+    #[test]
+    fn jumps_are_shifted_too() {
+        let mut marks = Marks::new();
+        marks.push_jump(0);
+        marks.push_jump(20);
+        marks.shift(&insert(10, "\n\n"));
+        assert_eq!(marks.back(30), Some(22));
+        assert_eq!(marks.back(22), Some(0));
+        marks.shift(&delete(0, "x"));
+        assert_eq!(marks.forward(), Some(21));
+        assert_eq!(marks.forward(), Some(29));
+    }
+
+    #[test]
+    fn other_changes_keep_the_marks() {
+        let mut marks = Marks::new();
+        marks.set('a', 4);
+        marks.shift(&Change::LineEnding {
+            from: LineEnding::Lf,
+            to: LineEnding::CrLf,
+        });
+        assert_eq!(marks.get('a'), Some(4));
+    }
+}
@@ -0,0 +1,196 @@
+use crate::writer::content::{EditorRows, Row};
+use crate::writer::highlight::{HighlightType, SyntaxHighlight};
+
+/// words allowed before the keyword of an item
+const MODIFIERS: [&str; 6] = ["pub", "unsafe", "async", "extern", "default", "const"];
+
+/// Symbol struct - an item of the buffer, from the row of its keyword to the end of its body
+pub struct Symbol {
+    pub kind: String,
+    pub name: String,
+    pub y: usize,
+    pub x: usize,
+    pub end: usize,
+}
+
+impl Symbol {
+    /// label shown in the picker
+    pub fn label(&self) -> String {
+        format!("{:>6}  {} {}", self.y + 1, self.kind, self.name)
+    }
+}
+
+/// Token struct - a word or a punctuation char of the code, strings and comments left out
+struct Token {
+    text: String,
+    keyword: bool,
+    y: usize,
+    x: usize,
+}
+
+/// Outline struct - the symbols of the buffer as of a number of changes
+pub struct Outline {
+    pub symbols: Vec<Symbol>,
+    pub changes: u64,
+}
+
+impl Outline {
+    /// find the items introduced by the symbol keywords of the syntax
+    pub fn scan(editor_rows: &EditorRows, syntax_highlight: &dyn SyntaxHighlight) -> Self {
+        let mut tokens = Vec::new();
+        for y in 0..editor_rows.number_of_rows() {
+            let mut row = editor_rows.get_editor_row(y);
+            syntax_highlight.highlight_row(&mut row, y > 0 && editor_rows.is_comment(y - 1));
+            tokenize(&row, y, &mut tokens);
+        }
+        let keywords = syntax_highlight.symbol_keywords();
+        let mut symbols: Vec<Symbol> = Vec::new();
+        let mut item_start = true;
+        let mut depth: usize = 0;
+        let mut nesting: usize = 0;
+        // the symbol waiting for its body, with the nesting of brackets at its keyword
+        let mut pending: Option<(usize, usize)> = None;
+        // the symbols whose body is open, with the depth of braces outside it
+        let mut open: Vec<(usize, usize)> = Vec::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            let next = tokens.get(i + 1).map(|it| it.text.as_str());
+            match token.text.as_str() {
+                "{" => {
+                    if let Some((index, _)) = pending.take() {
+                        open.push((index, depth));
+                    }
+                    depth += 1;
+                    item_start = true;
+                }
+                "}" => {
+                    depth = depth.saturating_sub(1);
+                    if let Some(&(index, _)) = open.last().filter(|(_, it)| *it == depth) {
+                        symbols[index].end = token.y;
+                        open.pop();
+                    }
+                    item_start = true;
+                }
+                ";" => {
+                    if pending.is_some_and(|(_, it)| nesting <= it) {
+                        pending = None;
+                    }
+                    item_start = true;
+                }
+                "(" | "[" => {
+                    nesting += 1;
+                    item_start = false;
+                }
+                ")" | "]" => {
+                    nesting = nesting.saturating_sub(1);
+                    item_start = token.text == "]";
+                }
+                "const"
+                    if item_start && matches!(next, Some("fn" | "unsafe" | "async" | "extern")) => {
+                }
+                text if item_start && token.keyword && keywords.contains(&text) => {
+                    let name = if text == "impl" {
+                        impl_header(&editor_rows.row_content(token.y), token.x + text.len())
+                    } else {
+                        next.filter(|it| it.starts_with(is_word_char))
+                            .unwrap_or_default()
+                            .into()
+                    };
+                    if !name.is_empty() {
+                        pending = Some((symbols.len(), nesting));
+                        symbols.push(Symbol {
+                            kind: text.into(),
+                            name,
+                            y: token.y,
+                            x: token.x,
+                            end: token.y,
+                        });
+                    }
+                    item_start = false;
+                }
+                "pub" if item_start && next == Some("(") => {
+                    while tokens.get(i).is_some_and(|it| it.text != ")") {
+                        i += 1;
+                    }
+                }
+                text if item_start && MODIFIERS.contains(&text) => {}
+                _ => item_start = false,
+            }
+            i += 1;
+        }
+        Self {
+            symbols,
+            changes: editor_rows.history.changes(),
+        }
+    }
+
+    /// the innermost function around the row
+    pub fn enclosing_function(&self, y: usize) -> Option<&Symbol> {
+        self.symbols
+            .iter()
+            .rfind(|it| it.kind == "fn" && it.y <= y && y <= it.end)
+    }
+}
+
+/// is word char
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// split the code of the row into tokens, keywords being the runs highlighted as such
+fn tokenize(row: &Row, y: usize, tokens: &mut Vec<Token>) {
+    let mut current: Option<Token> = None;
+    for (x, grapheme, highlight) in row.grapheme_highlights() {
+        let keyword = matches!(highlight, HighlightType::Other(_));
+        let word = keyword || grapheme.chars().all(is_word_char);
+        let code = highlight.is_code() && !grapheme.trim().is_empty();
+        if let Some(token) = current.as_mut() {
+            if code && word && token.keyword == keyword {
+                token.text.push_str(grapheme);
+                continue;
+            }
+            tokens.extend(current.take());
+        }
+        if !code {
+            continue;
+        }
+        let token = Token {
+            text: grapheme.into(),
+            keyword,
+            y,
+            x,
+        };
+        if word {
+            current = Some(token);
+        } else {
+            tokens.push(token);
+        }
+    }
+    tokens.extend(current);
+}
+
+/// what an impl block is for, from the rest of its row after the keyword
+fn impl_header(row_content: &str, after: usize) -> String {
+    let rest: String = row_content.chars().skip(after).collect();
+    let header = rest.split(['{', ';']).next().unwrap_or_default();
+    let header = header.split(" where").next().unwrap_or_default().trim();
+    // drop the generic parameters of the impl itself
+    let mut depth = 0;
+    let skip = if header.starts_with('<') {
+        header
+            .char_indices()
+            .find(|(_, c)| {
+                match c {
+                    '<' => depth += 1,
+                    '>' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            })
+            .map_or(0, |(i, _)| i + 1)
+    } else {
+        0
+    };
+    header[skip..].trim().into()
+}
@@ -0,0 +1,166 @@
+use std::cmp;
+
+/// Picker struct - a list of items narrowed down by the prompt input
+pub struct Picker {
+    items: Vec<String>,
+    pub filtered: Vec<usize>,
+    pub selected: usize,
+}
+
+impl Picker {
+    /// constructor - every item is shown at first
+    pub fn new(items: Vec<String>) -> Self {
+        let filtered = (0..items.len()).collect();
+        Self {
+            items,
+            filtered,
+            selected: 0,
+        }
+    }
+
+    /// keep the items matching the pattern, best first, shorter first among equals
+    pub fn filter(&mut self, pattern: &str) {
+        self.selected = 0;
+        if pattern.is_empty() {
+            self.filtered = (0..self.items.len()).collect();
+            return;
+        }
+        let mut scored: Vec<(i64, usize)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| fuzzy_score(pattern, item).map(|score| (score, i)))
+            .collect();
+        scored.sort_by_key(|&(score, i)| (cmp::Reverse(score), self.items[i].len(), i));
+        self.filtered = scored.into_iter().map(|(_, i)| i).collect();
+    }
+
+    /// select the next or the previous item, wrapping around
+    pub fn step(&mut self, forward: bool) {
+        let len = self.filtered.len();
+        if len == 0 {
+            return;
+        }
+        self.selected = if forward {
+            (self.selected + 1) % len
+        } else {
+            (self.selected + len - 1) % len
+        };
+    }
+
+    /// the index of the selected item
+    pub fn picked(&self) -> Option<usize> {
+        self.filtered.get(self.selected).copied()
+    }
+
+    /// the items shown in the height, scrolled to the selected one, and whether it is selected
+    pub fn visible(&self, height: usize) -> Vec<(&str, bool)> {
+        let start = (self.selected + 1).saturating_sub(height);
+        self.filtered
+            .iter()
+            .enumerate()
+            .skip(start)
+            .take(height)
+            .map(|(i, &item)| (self.items[item].as_str(), i == self.selected))
+            .collect()
+    }
+
+    /// counter shown after the prompt
+    pub fn counter(&self) -> String {
+        match self.filtered.len() {
+            0 => " no matches".into(),
+            len => format!(" {}/{}", self.selected + 1, len),
+        }
+    }
+}
+
+/// score of the pattern chars found in order in the candidate, ignoring case,
+/// higher for runs of chars and for chars starting a word or the file name
+pub fn fuzzy_score(pattern: &str, candidate: &str) -> Option<i64> {
+    const NONE: i64 = i64::MIN / 2;
+    let lowercase = |c: char| c.to_lowercase().next().unwrap_or(c);
+    let pattern: Vec<char> = pattern.chars().map(lowercase).collect();
+    let chars: Vec<char> = candidate.chars().collect();
+    let name_start = chars
+        .iter()
+        .rposition(|&c| c == '/' || c == '\\')
+        .map_or(0, |i| i + 1);
+    let bonus = |j: usize| {
+        let word_start = match j.checked_sub(1).map(|it| chars[it]) {
+            None => 8,
+            Some(previous) if !previous.is_alphanumeric() => 8,
+            Some(previous) if previous.is_lowercase() && chars[j].is_uppercase() => 6,
+            _ => 0,
+        };
+        word_start + if j >= name_start { 2 } else { 0 }
+    };
+    // the best scores with the last pattern char matched at a char, and at or before it
+    let mut matched = vec![NONE; chars.len()];
+    let mut best = vec![0; chars.len()];
+    for (i, &pattern_char) in pattern.iter().enumerate() {
+        let mut next_matched = vec![NONE; chars.len()];
+        let mut next_best = vec![NONE; chars.len()];
+        for j in 0..chars.len() {
+            let before = match j.checked_sub(1) {
+                _ if i == 0 => 0,
+                Some(k) => cmp::max(matched[k] + 8, best[k]),
+                None => NONE,
+            };
+            if lowercase(chars[j]) == pattern_char && before > NONE / 2 {
+                next_matched[j] = before + 16 + bonus(j);
+            }
+            // every char skipped costs a point
+            let carried = j.checked_sub(1).map_or(NONE, |k| next_best[k] - 1);
+            next_best[j] = cmp::max(next_matched[j], carried);
+        }
+        matched = next_matched;
+        best = next_best;
+    }
+    best.last().copied().filter(|&score| score > NONE / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_score_needs_the_chars_in_order() {
+        assert!(fuzzy_score("wrt", "src/writer.rs").is_some());
+        assert!(fuzzy_score("WRT", "src/writer.rs").is_some());
+        assert!(fuzzy_score("twr", "src/writer.rs").is_none());
+        assert!(fuzzy_score("writers", "writer").is_none());
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+    }
+
+    #[test]
+    fn fuzzy_score_prefers_runs_and_word_starts() {
+        let score = |candidate| fuzzy_score("con", candidate).unwrap();
+        assert!(score("content.rs") > score("cursor_controller.rs"));
+        assert!(score("src/content.rs") > score("src/icon.rs"));
+        assert!(score("the_content.rs") > score("thecontent.rs"));
+        assert!(score("readConfig") > score("reaDCOnfig"));
+    }
+
+    #[test]
+    fn fuzzy_score_prefers_the_file_name() {
+        let score = |candidate| fuzzy_score("main", candidate).unwrap();
+        assert!(score("src/main.rs") > score("main/src.rs"));
+    }
+
+    #[test]
+    fn filter_sorts_best_first_then_shorter() {
+        let mut picker = Picker::new(vec![
+            "src/writer/picker.rs".into(),
+            "src/editor.rs".into(),
+            "src/writer.rs".into(),
+        ]);
+        picker.filter("writer");
+        assert_eq!(picker.filtered, [2, 0]);
+        picker.step(false);
+        assert_eq!(picker.picked(), Some(0));
+        assert_eq!(picker.counter(), " 2/2");
+        picker.filter("zzz");
+        assert_eq!(picker.picked(), None);
+        assert_eq!(picker.counter(), " no matches");
+    }
+}
@@ -0,0 +1,319 @@
+use crate::writer::content::EditorRows;
+use crate::writer::highlight::SyntaxHighlight;
+use regex::{Captures, Regex, RegexBuilder};
+use std::cmp;
+use std::ops::Range;
+
+/// Case enum - how the case of letters is matched
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub enum Case {
+    /// insensitive unless the pattern has an uppercase letter
+    #[default]
+    Smart,
+    Sensitive,
+    Insensitive,
+}
+
+/// Search Options struct - toggled while searching, kept for the next search
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct SearchOptions {
+    pub case: Case,
+    pub whole_word: bool,
+    pub regex: bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            case: Case::Smart,
+            whole_word: false,
+            regex: true,
+        }
+    }
+}
+
+impl SearchOptions {
+    /// the next case mode - smart, sensitive, insensitive
+    pub fn toggle_case(&mut self) {
+        self.case = match self.case {
+            Case::Smart => Case::Sensitive,
+            Case::Sensitive => Case::Insensitive,
+            Case::Insensitive => Case::Smart,
+        }
+    }
+
+    /// the options shown after the prompt
+    pub fn describe(&self) -> String {
+        format!(
+            "[{}{}{}]",
+            match self.case {
+                Case::Smart => "smart-case",
+                Case::Sensitive => "case",
+                Case::Insensitive => "no case",
+            },
+            if self.whole_word { ", word" } else { "" },
+            if self.regex { ", regex" } else { "" }
+        )
+    }
+}
+
+/// Matcher struct - a pattern compiled with the search options
+pub struct Matcher {
+    pub regex: Regex,
+    whole_word: bool,
+}
+
+impl Matcher {
+    /// constructor - `^` and `$` match at every row, `\n` across rows
+    pub fn new(pattern: &str, options: &SearchOptions) -> Result<Self, regex::Error> {
+        let case_insensitive = match options.case {
+            Case::Smart => !pattern.chars().any(char::is_uppercase),
+            Case::Sensitive => false,
+            Case::Insensitive => true,
+        };
+        let pattern = if options.regex {
+            pattern.into()
+        } else {
+            regex::escape(pattern)
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(case_insensitive)
+            .multi_line(true)
+            .build()?;
+        Ok(Self {
+            regex,
+            whole_word: options.whole_word,
+        })
+    }
+
+    /// the first match not empty at or after the byte index,
+    /// for whole words one with separators on both sides
+    pub fn captures_at<'t>(
+        &self,
+        text: &'t str,
+        mut at: usize,
+        syntax_highlight: &dyn SyntaxHighlight,
+    ) -> Option<Captures<'t>> {
+        while at <= text.len() {
+            let captures = self.regex.captures_at(text, at)?;
+            let found = captures.get(0).unwrap();
+            let is_word = || {
+                let before = text[..found.start()].chars().next_back();
+                let after = text[found.end()..].chars().next();
+                [before, after]
+                    .into_iter()
+                    .all(|ch| ch.is_none_or(|ch| syntax_highlight.is_separator(ch)))
+            };
+            if !found.is_empty() && (!self.whole_word || is_word()) {
+                return Some(captures);
+            }
+            at = found.start()
+                + text[found.start()..]
+                    .chars()
+                    .next()
+                    .map_or(1, char::len_utf8);
+        }
+        None
+    }
+}
+
+/// Search struct - the matches of a pattern as char ranges of the buffer
+pub struct Search {
+    pub pattern: String,
+    pub error: Option<String>,
+    pub matches: Vec<Range<usize>>,
+    pub current: Option<usize>,
+    pub partial: bool,
+    pub saved_cursor: (usize, usize),
+    pub saved_offsets: (usize, usize),
+}
+
+impl Search {
+    /// constructor - remembers where the search started
+    pub fn new(saved_cursor: (usize, usize), saved_offsets: (usize, usize)) -> Self {
+        Self {
+            pattern: String::new(),
+            error: None,
+            matches: Vec::new(),
+            current: None,
+            partial: false,
+            saved_cursor,
+            saved_offsets,
+        }
+    }
+
+    /// find every match of the pattern in the buffer, rows joined by '\n',
+    /// in the rows read so far of a large file
+    pub fn find(
+        &mut self,
+        editor_rows: &EditorRows,
+        pattern: &str,
+        options: &SearchOptions,
+        syntax_highlight: &dyn SyntaxHighlight,
+    ) {
+        self.pattern = pattern.into();
+        self.error = None;
+        self.matches.clear();
+        self.current = None;
+        self.partial = editor_rows.is_loading();
+        if pattern.is_empty() {
+            return;
+        }
+        let matcher = match Matcher::new(pattern, options) {
+            Ok(matcher) => matcher,
+            Err(_) => {
+                self.error = Some("invalid pattern".into());
+                return;
+            }
+        };
+        let text = editor_rows.full_text();
+        let mut chars = 0;
+        let mut bytes = 0;
+        while let Some(captures) = matcher.captures_at(&text, bytes, syntax_highlight) {
+            let found = captures.get(0).unwrap();
+            chars += text[bytes..found.start()].chars().count();
+            let len = found.as_str().chars().count();
+            self.matches.push(chars..chars + len);
+            chars += len;
+            bytes = found.end();
+        }
+    }
+
+    /// select the first match at or after the char index, wrapping around
+    pub fn select_from(&mut self, at: usize) -> Option<&Range<usize>> {
+        if self.matches.is_empty() {
+            self.current = None;
+            return None;
+        }
+        let index = self.matches.partition_point(|it| it.start < at);
+        self.current = Some(index % self.matches.len());
+        self.current.map(|index| &self.matches[index])
+    }
+
+    /// select the next or the previous match, wrapping around
+    pub fn step(&mut self, forward: bool) -> Option<&Range<usize>> {
+        let len = self.matches.len();
+        let index = self.current?;
+        self.current = Some(if forward {
+            (index + 1) % len
+        } else {
+            (index + len - 1) % len
+        });
+        self.current.map(|index| &self.matches[index])
+    }
+
+    /// matches overlapping the char range
+    pub fn matches_in(&self, range: Range<usize>) -> &[Range<usize>] {
+        let start = self.matches.partition_point(|it| it.end <= range.start);
+        let end = self.matches.partition_point(|it| it.start < range.end);
+        &self.matches[start..cmp::max(start, end)]
+    }
+
+    /// counter shown after the prompt
+    pub fn counter(&self) -> String {
+        if self.pattern.is_empty() {
+            return String::new();
+        }
+        if let Some(error) = &self.error {
+            return format!(" {}", error);
+        }
+        match self.current {
+            Some(index) => format!(
+                " match {}/{}{}",
+                index + 1,
+                self.matches.len(),
+                if self.partial { "+" } else { "" }
+            ),
+            None => " no matches".into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::highlight::PlainHighlight;
+
+    fn options(case: Case, whole_word: bool, regex: bool) -> SearchOptions {
+        SearchOptions {
+            case,
+            whole_word,
+            regex,
+        }
+    }
+
+    fn found(pattern: &str, options: &SearchOptions, text: &str) -> Vec<usize> {
+        let matcher = Matcher::new(pattern, options).unwrap();
+        let plain = PlainHighlight::new();
+        let mut starts = Vec::new();
+        let mut at = 0;
+        while let Some(captures) = matcher.captures_at(text, at, &plain) {
+            let found = captures.get(0).unwrap();
+            starts.push(found.start());
+            at = found.end();
+        }
+        starts
+    }
+
+    #[test]
+    fn smart_case_follows_the_pattern() {
+        let smart = options(Case::Smart, false, false);
+        assert_eq!(found("foo", &smart, "foo Foo FOO"), [0, 4, 8]);
+        assert_eq!(found("Foo", &smart, "foo Foo FOO"), [4]);
+    }
+
+    #[test]
+    fn case_can_be_forced() {
+        let sensitive = options(Case::Sensitive, false, false);
+        assert_eq!(found("foo", &sensitive, "foo Foo"), [0]);
+        let insensitive = options(Case::Insensitive, false, false);
+        assert_eq!(found("Foo", &insensitive, "foo Foo"), [0, 4]);
+    }
+
+    #[test]
+    fn whole_word_needs_separators_around() {
+        let word = options(Case::Smart, true, false);
+        assert_eq!(
+            found("foo", &word, "foo foobar bar_foo (foo)\nfoo"),
+            [0, 20, 25]
+        );
+    }
+
+    #[test]
+    fn whole_word_tries_a_later_match_in_a_word() {
+        let word = options(Case::Smart, true, true);
+        assert_eq!(found("a+", &word, "baa aa"), [4]);
+    }
+
+    #[test]
+    fn plain_patterns_are_escaped() {
+        let plain = options(Case::Smart, false, false);
+        assert_eq!(found("a.c", &plain, "abc a.c"), [4]);
+        let regex = options(Case::Smart, false, true);
+        assert_eq!(found("a.c", &regex, "abc a.c"), [0, 4]);
+    }
+
+    #[test]
+    fn anchors_match_at_every_row_and_empty_matches_are_skipped() {
+        let regex = options(Case::Smart, false, true);
+        assert_eq!(found("^b", &regex, "ab\nb\n"), [3]);
+        assert_eq!(found("x*", &regex, "ax\nxx"), [1, 3]);
+    }
+
+    #[test]
+    fn find_steps_through_char_ranges() {
+        let editor_rows = EditorRows::from_rows(["één een", "een"].into_iter());
+        let mut search = Search::new((0, 0), (0, 0));
+        search.find(
+            &editor_rows,
+            "een",
+            &options(Case::Smart, false, false),
+            &PlainHighlight::new(),
+        );
+        assert_eq!(search.matches, [4..7, 8..11]);
+        assert_eq!(search.select_from(5), Some(&(8..11)));
+        assert_eq!(search.step(true), Some(&(4..7)));
+        assert_eq!(search.step(false), Some(&(8..11)));
+        assert_eq!(search.counter(), " match 2/2");
+    }
+}
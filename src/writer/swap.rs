@@ -0,0 +1,170 @@
+use crate::writer::content::EditorRows;
+use crate::writer::file;
+use crate::{SWAP_INTERVAL, TAB_SIZE};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{env, fs, io, process};
+
+/// Swap File struct - a periodic copy of the unsaved buffer to recover after a crash
+#[derive(Default)]
+pub struct SwapFile {
+    path: Option<PathBuf>,
+    written_at: Option<Instant>,
+    written_changes: u64,
+}
+
+impl SwapFile {
+    /// constructor
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// swap file next to the edited file
+    pub fn path_beside(filename: &Path) -> PathBuf {
+        let filename = file::resolve_symlinks(filename);
+        let name = filename
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        filename.with_file_name(format!(".{}.swp", name))
+    }
+
+    /// swap file in the state directory, used when the one beside can't be written
+    pub fn path_in_state_dir(filename: Option<&Path>) -> Option<PathBuf> {
+        let dir = env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))?
+            .join("rim/swap");
+        let name = match filename {
+            Some(filename) => {
+                let filename = env::current_dir()
+                    .map(|dir| dir.join(filename))
+                    .unwrap_or_else(|_| filename.to_path_buf());
+                filename.to_string_lossy().replace(['/', '\\'], "%")
+            }
+            None => format!("unnamed-{}", process::id()),
+        };
+        Some(dir.join(format!("{}.swp", name)))
+    }
+
+    /// an existing swap file of the file that is newer than it
+    pub fn find(filename: &Path) -> Option<PathBuf> {
+        let modified = fs::metadata(filename).and_then(|it| it.modified()).ok();
+        [
+            Some(Self::path_beside(filename)),
+            Self::path_in_state_dir(Some(filename)),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|path| {
+            fs::metadata(path)
+                .and_then(|it| it.modified())
+                .map(|swapped| modified.map(|modified| swapped > modified).unwrap_or(true))
+                .unwrap_or(false)
+        })
+    }
+
+    /// write the swap file if the buffer changed since the last write a while ago,
+    /// once the whole file is read
+    pub fn update(&mut self, editor_rows: &EditorRows) -> io::Result<()> {
+        let changes = editor_rows.history.changes();
+        let due = self
+            .written_at
+            .map(|time| time.elapsed() >= Duration::from_secs(SWAP_INTERVAL))
+            .unwrap_or(true);
+        if !due || changes == self.written_changes || editor_rows.is_loading() {
+            return Ok(());
+        }
+        if !editor_rows.history.is_modified() {
+            self.remove();
+            self.written_changes = changes;
+            return Ok(());
+        }
+        let write = |path: &Path| {
+            file::write_atomically(path, false, |out| editor_rows.write_contents(out))
+        };
+        let path = match &self.path {
+            Some(path) => write(path).map(|_| path.clone()),
+            None => editor_rows
+                .filename
+                .as_deref()
+                .map(Self::path_beside)
+                .ok_or_else(|| io::Error::other("no file name"))
+                .and_then(|path| write(&path).map(|_| path))
+                .or_else(|_| {
+                    let path = Self::path_in_state_dir(editor_rows.filename.as_deref())
+                        .ok_or_else(|| io::Error::other("no state directory"))?;
+                    fs::create_dir_all(path.parent().unwrap())?;
+                    write(&path).map(|_| path)
+                }),
+        }?;
+        self.path = Some(path);
+        self.written_at = Some(Instant::now());
+        self.written_changes = changes;
+        Ok(())
+    }
+
+    /// delete the swap file written for the buffer
+    pub fn remove(&mut self) {
+        if let Some(path) = self.path.take() {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// take over a swap file found for the buffer
+    pub fn adopt(&mut self, path: PathBuf) {
+        self.remove();
+        self.path = Some(path);
+    }
+}
+
+/// the rows of the buffer and of the swap file, and the numbers of rows alike at both ends
+fn diff<'a>(
+    editor_rows: &'a EditorRows,
+    swapped: &'a str,
+) -> (Vec<Cow<'a, str>>, Vec<&'a str>, usize, usize) {
+    let rows: Vec<_> = (0..editor_rows.number_of_rows())
+        .map(|at| editor_rows.row_content(at))
+        .collect();
+    let swapped: Vec<_> = swapped.lines().collect();
+    let prefix = rows
+        .iter()
+        .zip(&swapped)
+        .take_while(|(row, swapped)| row == swapped)
+        .count();
+    let suffix = rows[prefix..]
+        .iter()
+        .rev()
+        .zip(swapped[prefix..].iter().rev())
+        .take_while(|(row, swapped)| row == swapped)
+        .count();
+    (rows, swapped, prefix, suffix)
+}
+
+/// summary of the rows that differ between the buffer and the swap file
+pub fn diff_summary(editor_rows: &EditorRows, swapped: &str) -> String {
+    let (rows, swapped, prefix, suffix) = diff(editor_rows, swapped);
+    if prefix == rows.len() && prefix == swapped.len() {
+        return "Swap file is the same as the file".into();
+    }
+    format!(
+        "Swap file differs from line {}: {} lines in the file, {} in the swap file",
+        prefix + 1,
+        rows.len() - prefix - suffix,
+        swapped.len() - prefix - suffix
+    )
+}
+
+/// the rows that differ, `-` for the ones of the file and `+` for the ones of the swap file
+pub fn diff_rows(editor_rows: &EditorRows, swapped: &str) -> Vec<String> {
+    let (rows, swapped, prefix, suffix) = diff(editor_rows, swapped);
+    let expand = |row: &str| row.replace('\t', &" ".repeat(TAB_SIZE));
+    let removed = rows[prefix..rows.len() - suffix]
+        .iter()
+        .map(|row| format!("- {}", expand(row)));
+    let added = swapped[prefix..swapped.len() - suffix]
+        .iter()
+        .map(|row| format!("+ {}", expand(row)));
+    removed.chain(added).collect()
+}